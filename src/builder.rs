@@ -0,0 +1,133 @@
+use crate::errors::ParseError;
+use crate::regex::Regex;
+use crate::{lazy_dfa, nfa, parser, tokenizer};
+
+/// Compiles a [`Regex`] with options a pattern alone cannot set.
+///
+/// The flags set the initial state of their inline counterparts, which
+/// the pattern can still override. The limits bound the work and memory
+/// spent on untrusted patterns, each failing the build with its own
+/// [`ParseError`] when exceeded.
+///
+/// ```
+/// use regex_engine::RegexBuilder;
+///
+/// let regex = RegexBuilder::new(r"^ \w+ : \s* (\d+)  # a key and its value")
+///     .multi_line(true)
+///     .ignore_whitespace(true)
+///     .build()
+///     .unwrap();
+/// let caps = regex.captures("width: 4\nheight: 20").unwrap();
+/// assert_eq!(caps.get(1).unwrap().as_str(), "4");
+///
+/// let regex = RegexBuilder::new("straße").case_insensitive(true).build();
+/// assert!(regex.unwrap().is_match("STRAẞE"));
+///
+/// assert!(RegexBuilder::new(r"\w{100}").size_limit(1 << 10).build().is_err());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    case_insensitive: bool,
+    multi_line: bool,
+    dot_matches_new_line: bool,
+    ignore_whitespace: bool,
+    unicode: bool,
+    size_limit: usize,
+    nest_limit: usize,
+    repetition_limit: u32,
+    dfa_cache_capacity: usize,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        Self {
+            pattern: pattern.into(),
+            case_insensitive: false,
+            multi_line: false,
+            dot_matches_new_line: false,
+            ignore_whitespace: false,
+            unicode: true,
+            size_limit: nfa::DEFAULT_SIZE_LIMIT,
+            nest_limit: parser::DEFAULT_NEST_LIMIT,
+            repetition_limit: parser::DEFAULT_REPETITION_LIMIT,
+            dfa_cache_capacity: lazy_dfa::DEFAULT_CACHE_CAPACITY,
+        }
+    }
+
+    /// Lets letters match regardless of their case, like `(?i)`.
+    pub fn case_insensitive(mut self, yes: bool) -> Self {
+        self.case_insensitive = yes;
+        self
+    }
+
+    /// Lets `^` and `$` also match at the start and end of lines, like
+    /// `(?m)`.
+    pub fn multi_line(mut self, yes: bool) -> Self {
+        self.multi_line = yes;
+        self
+    }
+
+    /// Lets `.` also match `\n`, like `(?s)`.
+    pub fn dot_matches_new_line(mut self, yes: bool) -> Self {
+        self.dot_matches_new_line = yes;
+        self
+    }
+
+    /// Ignores whitespace and `#` comments up to the end of the line
+    /// outside char classes, like `(?x)`. `\ ` and `\#` still match a space
+    /// and a `#`.
+    pub fn ignore_whitespace(mut self, yes: bool) -> Self {
+        self.ignore_whitespace = yes;
+        self
+    }
+
+    /// Uses the Unicode definitions of `\d`, `\w`, `\s` and of case
+    /// folding, on by default. Off, like `(?-u)`, they only cover ASCII.
+    pub fn unicode(mut self, yes: bool) -> Self {
+        self.unicode = yes;
+        self
+    }
+
+    /// Fails with [`ParseError::SizeLimitExceeded`] when a compiled
+    /// program would take more than `bytes`, 10 MiB by default.
+    pub fn size_limit(mut self, bytes: usize) -> Self {
+        self.size_limit = bytes;
+        self
+    }
+
+    /// Fails with [`ParseError::NestLimitExceeded`] when groups nest deeper
+    /// than `limit`, 250 by default.
+    pub fn nest_limit(mut self, limit: usize) -> Self {
+        self.nest_limit = limit;
+        self
+    }
+
+    /// Fails with [`ParseError::RepetitionLimitExceeded`] when a `{n,m}`
+    /// count is above `limit`, 1000 by default.
+    pub fn repetition_limit(mut self, limit: u32) -> Self {
+        self.repetition_limit = limit;
+        self
+    }
+
+    /// Lets the lazy DFA used for searching take up to `capacity` bytes, 2
+    /// MiB by default. Searches that would need more fall back to a slower
+    /// engine.
+    pub fn dfa_cache_capacity(mut self, capacity: usize) -> Self {
+        self.dfa_cache_capacity = capacity;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, ParseError> {
+        let tokens = tokenizer::tokenize_with(self.pattern.clone(), self.ignore_whitespace)?;
+        let ast = parser::Parser::new(&tokens)
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multi_line)
+            .dot_matches_new_line(self.dot_matches_new_line)
+            .unicode(self.unicode)
+            .nest_limit(self.nest_limit)
+            .repetition_limit(self.repetition_limit)
+            .parse()?;
+        Regex::from_ast(ast, self.size_limit, self.dfa_cache_capacity)
+    }
+}
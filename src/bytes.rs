@@ -0,0 +1,223 @@
+//! Regexes searching arbitrary bytes, such as binary data or text that is
+//! not valid UTF-8.
+//!
+//! Patterns are Unicode-aware by default, in which case `.`, classes and
+//! `\xHH` escapes only match whole UTF-8 encoded chars. Inside `(?-u)` they
+//! match single bytes instead: `.` matches any byte but `\n`, `\xFF` the byte
+//! `0xFF` and `[\x80-\xFF]` any byte in that range.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::{errors, lazy_dfa, nfa, parser, regex, tokenizer, EngineInfo};
+
+/// A match in a haystack, borrowing the matched bytes from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'h> Match<'h> {
+    /// Offset of the start of the match in the haystack.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Offset of the end of the match in the haystack.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Offsets of the match in the haystack.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn as_bytes(&self) -> &'h [u8] {
+        &self.haystack[self.start..self.end]
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    fn new(haystack: &'h [u8], start: usize, end: usize) -> Self {
+        Self {
+            haystack,
+            start,
+            end,
+        }
+    }
+}
+
+/// The groups matched by [`Regex::captures`]. Group 0 is the whole match,
+/// the other groups are numbered by the position of their opening paren.
+#[derive(Debug)]
+pub struct Captures<'h> {
+    haystack: &'h [u8],
+    slots: Vec<Option<usize>>,
+    names: Arc<[Option<String>]>,
+}
+
+impl<'h> Captures<'h> {
+    /// Returns the match of group `i`, or `None` if the group did not
+    /// participate in the match or does not exist.
+    pub fn get(&self, i: usize) -> Option<Match<'h>> {
+        match (self.slots.get(2 * i)?, self.slots.get(2 * i + 1)?) {
+            (Some(start), Some(end)) => Some(Match::new(self.haystack, *start, *end)),
+            _ => None,
+        }
+    }
+
+    /// Returns the match of the group called `name`, or `None` if there is
+    /// no such group or it did not participate in the match.
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        let index = self.names.iter().position(|n| n.as_deref() == Some(name))?;
+        self.get(index)
+    }
+
+    /// Number of groups in the pattern, including group 0.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    /// Iterates over every group in order, yielding `None` for the groups
+    /// that did not participate in the match.
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match<'h>>> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+}
+
+/// A compiled regex searching `&[u8]` haystacks.
+#[derive(Debug)]
+pub struct Regex {
+    regex: regex::Regex,
+}
+
+impl Regex {
+    pub fn new(pattern: impl Into<String>) -> Result<Self, errors::ParseError> {
+        let tokens = tokenizer::tokenize(pattern.into())?;
+        let ast = parser::Parser::new(&tokens)
+            .allow_invalid_utf8(true)
+            .parse()?;
+        Ok(Self {
+            regex: regex::Regex::from_ast(
+                ast,
+                nfa::DEFAULT_SIZE_LIMIT,
+                lazy_dfa::DEFAULT_CACHE_CAPACITY,
+            )?,
+        })
+    }
+
+    /// Names of the groups indexed by group number, starting with group 0.
+    /// Unnamed groups, including group 0, yield `None`.
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> + '_ {
+        self.regex.capture_names()
+    }
+
+    /// Tells which search path was picked for the pattern.
+    pub fn engine_info(&self) -> EngineInfo {
+        self.regex.engine_info()
+    }
+
+    /// Returns whether the pattern matches anywhere in `haystack`, which is
+    /// faster than finding where.
+    pub fn is_match(&self, haystack: impl AsRef<[u8]>) -> bool {
+        self.regex.is_match_bytes(haystack.as_ref())
+    }
+
+    /// Returns the leftmost-first match in `haystack`, or `None` if the
+    /// pattern does not match.
+    pub fn find<'h, H>(&self, haystack: &'h H) -> Option<Match<'h>>
+    where
+        H: AsRef<[u8]> + ?Sized,
+    {
+        let haystack = haystack.as_ref();
+        let mut slots = [None; 2];
+
+        match self.regex.search_bytes(haystack, 0, false, &mut slots) {
+            true => Some(Match::new(haystack, slots[0].unwrap(), slots[1].unwrap())),
+            false => None,
+        }
+    }
+
+    /// Returns the groups of the leftmost-first match, or `None` if the
+    /// pattern does not match.
+    pub fn captures<'h>(&self, haystack: &'h [u8]) -> Option<Captures<'h>> {
+        let names = self.regex.capture_names.clone();
+        let mut slots = vec![None; 2 * names.len()];
+
+        match self.regex.search_bytes(haystack, 0, false, &mut slots) {
+            true => Some(Captures {
+                haystack,
+                slots,
+                names,
+            }),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_utf8_haystack() {
+        let reg = Regex::new(r#"b+"#).unwrap();
+        let m = reg.find(b"\xFFa\xC3bb\x80").unwrap();
+
+        assert_eq!(m.range(), 3..5);
+        assert_eq!(m.as_bytes(), b"bb");
+        assert_eq!(m.len(), 2);
+    }
+
+    #[test]
+    fn test_unicode_mode() {
+        // Unicode `.` and `\xFF` only match whole chars
+        let reg = Regex::new(r#"a.b"#).unwrap();
+        assert!(reg.find(b"a\xFFb").is_none());
+        assert_eq!(reg.find("aéb").unwrap().range(), 0..4);
+
+        let reg = Regex::new(r#"\xFF"#).unwrap();
+        assert!(reg.find(b"\xFF").is_none());
+        assert!(reg.find("ÿ").is_some());
+    }
+
+    #[test]
+    fn test_byte_mode() {
+        let reg = Regex::new(r#"(?-u)a.b"#).unwrap();
+        assert!(reg.find(b"a\xFFb").is_some());
+        assert!(reg.find("aéb").is_none());
+        // Like any `.`, it only matches `\n` with the `s` flag
+        assert!(reg.find("a\nb").is_none());
+        assert!(Regex::new(r#"(?s-u)a.b"#).unwrap().find("a\nb").is_some());
+
+        let reg = Regex::new(r#"(?-u:\xFF+)\xFF"#).unwrap();
+        assert_eq!(reg.find(b"\xFF\xFF\xC3\xBF").unwrap().range(), 0..4);
+
+        let reg = Regex::new(r#"(?-u)[\x80-\xFF]+"#).unwrap();
+        assert_eq!(reg.find(b"ab\x80\xFEc").unwrap().range(), 2..4);
+
+        let reg = Regex::new(r#"(?-u)[^a]\W"#).unwrap();
+        assert_eq!(reg.find(b"a\xE9\xFF").unwrap().range(), 1..3);
+
+        assert!(Regex::new(r#"(?-u)[é]"#).is_err());
+    }
+
+    #[test]
+    fn test_byte_captures() {
+        let reg = Regex::new(r#"(?s-u)(?P<magic>\x89PNG)(.+)"#).unwrap();
+        let caps = reg.captures(b"\x00\x89PNG\r\n\x1A\n").unwrap();
+
+        assert_eq!(caps.name("magic").unwrap().range(), 1..5);
+        assert_eq!(caps.get(2).unwrap().as_bytes(), b"\r\n\x1A\n");
+    }
+}
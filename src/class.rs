@@ -0,0 +1,264 @@
+use crate::tokenizer::EscapeChar;
+use crate::unicode_tables;
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// A set of chars, stored as sorted ranges that neither overlap nor touch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharClass {
+    ranges: Box<[(char, char)]>,
+    negated: bool,
+}
+
+impl CharClass {
+    /// Builds the class matching every char in `ranges`, or every char not
+    /// in them when `negated`. Ranges are inclusive and may be given in any
+    /// order, overlapping or not.
+    pub fn new(ranges: impl IntoIterator<Item = (char, char)>, negated: bool) -> Self {
+        let mut ranges: Vec<_> = ranges.into_iter().collect();
+        ranges.sort_unstable();
+
+        let mut merged: Vec<(char, char)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some((_, last_end)) if start <= *last_end || Some(start) == succ(*last_end) => {
+                    *last_end = end.max(*last_end);
+                }
+                _ => merged.push((start, end)),
+            }
+        }
+
+        Self {
+            ranges: merged.into(),
+            negated,
+        }
+    }
+
+    /// Builds the class for a Perl shorthand such as `\d`, using either the
+    /// ASCII or the Unicode definition. Returns `None` for escapes that
+    /// stand for a single char.
+    pub fn perl(escape: EscapeChar, unicode: bool) -> Option<Self> {
+        let ranges: &[(char, char)] = match (escape, unicode) {
+            (EscapeChar::Digit | EscapeChar::NotDigit, false) => &[('0', '9')],
+            (EscapeChar::Digit | EscapeChar::NotDigit, true) => unicode_tables::PERL_DIGIT,
+            (EscapeChar::Space | EscapeChar::NotSpace, false) => &[('\t', '\r'), (' ', ' ')],
+            (EscapeChar::Space | EscapeChar::NotSpace, true) => unicode_tables::PERL_SPACE,
+            (EscapeChar::Word | EscapeChar::NotWord, false) => {
+                &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]
+            }
+            (EscapeChar::Word | EscapeChar::NotWord, true) => unicode_tables::PERL_WORD,
+            (EscapeChar::Newline | EscapeChar::Tab | EscapeChar::Null, _) => return None,
+        };
+        let negated = matches!(
+            escape,
+            EscapeChar::NotDigit | EscapeChar::NotSpace | EscapeChar::NotWord
+        );
+
+        Some(Self::new(ranges.iter().copied(), negated))
+    }
+
+    /// Adds the other cases of every char in the class, following Unicode
+    /// simple case folding, or only for ASCII letters unless `unicode`. A
+    /// negated class stays negated, so it then excludes every case.
+    pub fn case_fold(&self, unicode: bool) -> Self {
+        let pairs = fold_pairs();
+        let mut ranges = self.ranges.to_vec();
+        for &(start, end) in self.ranges.iter() {
+            let from = pairs.partition_point(|&(c, _)| c < start);
+            let others = pairs[from..]
+                .iter()
+                .take_while(|&&(c, _)| c <= end)
+                .filter(|&&(c, other)| unicode || (c.is_ascii() && other.is_ascii()))
+                .map(|&(_, other)| (other, other));
+            ranges.extend(others);
+        }
+        Self::new(ranges, self.negated)
+    }
+
+    /// The ranges of chars this class matches, with negation applied.
+    pub fn matched_ranges(&self) -> Vec<(char, char)> {
+        if !self.negated {
+            return self.ranges.to_vec();
+        }
+
+        let mut complement = vec![];
+        let mut next = Some('\0');
+        for &(start, end) in self.ranges.iter() {
+            if let Some(from) = next.filter(|&from| from < start) {
+                complement.push((from, pred(start)));
+            }
+            next = succ(end);
+        }
+        if let Some(from) = next {
+            complement.push((from, char::MAX));
+        }
+        complement
+    }
+}
+
+/// Every pair of distinct chars that are cases of each other, sorted.
+///
+/// Chars are grouped by their lowercased uppercase, counting only mappings
+/// to a single char, which yields the simple case folding classes.
+fn fold_pairs() -> &'static [(char, char)] {
+    static PAIRS: OnceLock<Box<[(char, char)]>> = OnceLock::new();
+    PAIRS.get_or_init(|| {
+        fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+            let c = chars.next();
+            chars.next().is_none().then_some(c).flatten()
+        }
+
+        let mut classes: HashMap<char, Vec<char>> = HashMap::new();
+        // Cased chars all sit in the first two planes. The dotless `ı`
+        // uppercases to `I` but does not fold with it.
+        for c in ('\0'..='\u{1FFFF}').filter(|&c| c != 'ı') {
+            let upper = single(c.to_uppercase()).unwrap_or(c);
+            let key = single(upper.to_lowercase()).unwrap_or(upper);
+            classes.entry(key).or_default().push(c);
+        }
+
+        let mut pairs: Vec<_> = classes
+            .values()
+            .flat_map(|class| {
+                class.iter().flat_map(move |&c| {
+                    class
+                        .iter()
+                        .filter(move |&&other| other != c)
+                        .map(move |&other| (c, other))
+                })
+            })
+            .collect();
+        pairs.sort_unstable();
+        pairs.into()
+    })
+}
+
+/// The char right before `c`, skipping the surrogate gap. `c` must not be
+/// `'\0'`.
+fn pred(c: char) -> char {
+    match c {
+        '\u{E000}' => '\u{D7FF}',
+        c => char::from_u32(c as u32 - 1).unwrap(),
+    }
+}
+
+/// The char right after `c`, skipping the surrogate gap.
+fn succ(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl CharClass {
+        fn contains(&self, c: char) -> bool {
+            let found = self
+                .ranges
+                .binary_search_by(|&(start, end)| {
+                    if end < c {
+                        std::cmp::Ordering::Less
+                    } else if start > c {
+                        std::cmp::Ordering::Greater
+                    } else {
+                        std::cmp::Ordering::Equal
+                    }
+                })
+                .is_ok();
+
+            found != self.negated
+        }
+    }
+
+    #[test]
+    fn test_merge_ranges() {
+        let class = CharClass::new([('x', 'z'), ('a', 'c'), ('b', 'f'), ('g', 'g')], false);
+        assert_eq!(&*class.ranges, &[('a', 'g'), ('x', 'z')]);
+
+        let class = CharClass::new([('\u{E000}', '\u{E001}'), ('\u{D000}', '\u{D7FF}')], false);
+        assert_eq!(&*class.ranges, &[('\u{D000}', '\u{E001}')]);
+    }
+
+    #[test]
+    fn test_contains() {
+        let class = CharClass::new([('a', 'c'), ('x', 'x')], false);
+
+        assert!(class.contains('a'));
+        assert!(class.contains('b'));
+        assert!(class.contains('x'));
+        assert!(!class.contains('d'));
+        assert!(!class.contains('w'));
+        assert!(!class.contains('é'));
+    }
+
+    #[test]
+    fn test_negated_contains() {
+        let class = CharClass::new([('0', '9')], true);
+
+        assert!(!class.contains('0'));
+        assert!(!class.contains('5'));
+        assert!(class.contains('a'));
+        assert!(class.contains('é'));
+    }
+
+    #[test]
+    fn test_matched_ranges() {
+        let class = CharClass::new([('\0', 'a'), ('z', 'z'), ('\u{E000}', '\u{E000}')], true);
+        assert_eq!(
+            class.matched_ranges(),
+            vec![('b', 'y'), ('{', '\u{D7FF}'), ('\u{E001}', char::MAX)]
+        );
+
+        let class = CharClass::new([], true);
+        assert_eq!(class.matched_ranges(), vec![('\0', char::MAX)]);
+    }
+
+    #[test]
+    fn test_perl_classes() {
+        let digit = CharClass::perl(EscapeChar::Digit, false).unwrap();
+        assert!(digit.contains('7'));
+        assert!(!digit.contains('٣'));
+
+        let digit = CharClass::perl(EscapeChar::Digit, true).unwrap();
+        assert!(digit.contains('٣'));
+        assert!(!digit.contains('²'));
+
+        let not_word = CharClass::perl(EscapeChar::NotWord, true).unwrap();
+        assert!(not_word.contains(' '));
+        assert!(!not_word.contains('ß'));
+
+        let space = CharClass::perl(EscapeChar::Space, false).unwrap();
+        assert!(space.contains('\x0B'));
+        assert!(!space.contains('\u{A0}'));
+
+        assert!(CharClass::perl(EscapeChar::Tab, true).is_none());
+    }
+
+    #[test]
+    fn test_case_fold() {
+        let class = CharClass::new([('a', 'c'), ('7', '7')], false).case_fold(true);
+        assert_eq!(&*class.ranges, &[('7', '7'), ('A', 'C'), ('a', 'c')]);
+
+        let class = CharClass::new([('k', 'k')], false);
+        assert!(class.case_fold(true).contains('\u{212A}'));
+        assert!(!class.case_fold(false).contains('\u{212A}'));
+        assert!(class.case_fold(false).contains('K'));
+
+        let sigma = CharClass::new([('ς', 'ς')], false).case_fold(true);
+        assert!(sigma.contains('Σ') && sigma.contains('σ'));
+        assert!(!CharClass::new([('i', 'i')], false)
+            .case_fold(true)
+            .contains('ı'));
+        assert!(!CharClass::new([('é', 'é')], false)
+            .case_fold(false)
+            .contains('É'));
+
+        let negated = CharClass::new([('x', 'x')], true).case_fold(true);
+        assert!(!negated.contains('X'));
+        assert!(negated.contains('y'));
+    }
+}
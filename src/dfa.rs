@@ -0,0 +1,500 @@
+//! Fully compiled DFAs, built once from a [`Regex`] and then serialized so
+//! they can be loaded without parsing or determinizing anything.
+//!
+//! ```
+//! use regex_engine::dfa::Dfa;
+//! use regex_engine::Regex;
+//!
+//! let dfa = Dfa::from_regex(&Regex::new(r"\d+").unwrap()).unwrap();
+//! let bytes = dfa.to_bytes();
+//!
+//! let dfa = Dfa::from_bytes(&bytes).unwrap();
+//! assert_eq!(dfa.find("abc 123").unwrap().range(), 4..7);
+//! ```
+
+use crate::errors::DfaError;
+use crate::lazy_dfa::{Context, Determinized, Direction, LazyDfa, DEAD};
+use crate::regex::{Match, Regex};
+
+/// Maximum number of states of each of the DFAs of a [`Dfa`].
+pub const STATE_LIMIT: usize = 10_000;
+
+const MAGIC: &[u8; 8] = b"RGXDFA02";
+
+/// A regex compiled to a pair of minimized DFAs: a forward one finding
+/// where the leftmost-first match ends, and a reverse one finding where it
+/// starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dfa {
+    forward: Table,
+    reverse: Table,
+}
+
+/// The transition table of a DFA. State 0 is the dead state.
+#[derive(Debug, Clone, PartialEq)]
+struct Table {
+    /// `next[256 * state + byte]` is the state reached on `byte`
+    next: Vec<u32>,
+    is_match: Vec<bool>,
+    /// Whether the input ending in a state makes it a match state
+    eoi_match: Vec<bool>,
+    /// Whether a `\n` coming next makes a state a match state
+    eol_match: Vec<bool>,
+    /// Start states, indexed by the [`Context`] before the search
+    starts: [u32; 3],
+}
+
+impl Dfa {
+    /// Builds the DFAs of `regex`, failing if either would need more than
+    /// [`STATE_LIMIT`] states before minimization.
+    pub fn from_regex(regex: &Regex) -> Result<Self, DfaError> {
+        let build = |program, direction| {
+            LazyDfa::new(program, direction)
+                .determinize(STATE_LIMIT)
+                .map(|dfa| Table::minimized(&dfa))
+                .ok_or(DfaError::TooManyStates(STATE_LIMIT))
+        };
+
+        Ok(Self {
+            forward: build(&regex.program, Direction::Forward)?,
+            reverse: build(&regex.reverse, Direction::Reverse)?,
+        })
+    }
+
+    /// Serializes both DFAs, to be loaded back with [`Dfa::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        self.forward.write(&mut bytes);
+        self.reverse.write(&mut bytes);
+        bytes
+    }
+
+    /// Loads DFAs serialized by [`Dfa::to_bytes`], checking that they are
+    /// well formed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DfaError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(DfaError::BadSerialization("missing header".into()));
+        }
+
+        let forward = Table::read(&mut reader)?;
+        let reverse = Table::read(&mut reader)?;
+        if !reader.bytes.is_empty() {
+            return Err(DfaError::BadSerialization("trailing bytes".into()));
+        }
+        Ok(Self { forward, reverse })
+    }
+
+    /// Returns whether the regex matches anywhere in `s`.
+    pub fn is_match(&self, s: impl AsRef<str>) -> bool {
+        let s = s.as_ref().as_bytes();
+        let forward = s.iter().copied();
+        self.forward.search(forward, Context::Text, true).is_some()
+    }
+
+    /// Finds the leftmost-first match, like [`Regex::find`].
+    pub fn find<'h>(&self, s: &'h str) -> Option<Match<'h>> {
+        let bytes = s.as_bytes();
+        let end = self
+            .forward
+            .search(bytes.iter().copied(), Context::Text, false)?;
+        // Only a corrupt DFA can end a match inside a char
+        if !s.is_char_boundary(end) {
+            return None;
+        }
+
+        // Reversed, the input starts where it used to end
+        let reversed = bytes[..end].iter().rev().copied();
+        let behind = Context::of(bytes.get(end).copied());
+        match self.reverse.search(reversed, behind, false) {
+            Some(len) if s.is_char_boundary(end - len) => Some(Match::new(s, end - len, end)),
+            // Only a corrupt DFA can disagree with itself
+            _ => None,
+        }
+    }
+}
+
+impl Table {
+    /// Minimizes `dfa` with Hopcroft's algorithm: states are split into
+    /// blocks until states in the same block behave the same on every byte,
+    /// then each block becomes a single state.
+    fn minimized(dfa: &Determinized) -> Self {
+        let len = dfa.next.len();
+
+        // The sources of the transitions on each byte, grouped by target
+        let mut offsets = vec![0; 256 * (len + 1)];
+        for next in &dfa.next {
+            for (byte, &target) in next.iter().enumerate() {
+                offsets[byte * (len + 1) + target as usize + 1] += 1;
+            }
+        }
+        for byte in 0..256 {
+            for target in 0..len {
+                offsets[byte * (len + 1) + target + 1] += offsets[byte * (len + 1) + target];
+            }
+        }
+        let mut sources = vec![0; 256 * len];
+        let mut filled = offsets.clone();
+        for (source, next) in dfa.next.iter().enumerate() {
+            for (byte, &target) in next.iter().enumerate() {
+                let i = &mut filled[byte * (len + 1) + target as usize];
+                sources[byte * len + *i] = source as u32;
+                *i += 1;
+            }
+        }
+
+        let mut blocks = Blocks::new(dfa);
+        let mut pending: Vec<usize> = (0..blocks.members.len()).collect();
+        let mut is_pending = vec![true; blocks.members.len()];
+        let mut touched: Vec<Vec<u32>> = vec![];
+        let mut touched_blocks = vec![];
+
+        while let Some(splitter) = pending.pop() {
+            is_pending[splitter] = false;
+            let splitter = blocks.members[splitter].clone();
+
+            for byte in 0..256 {
+                // Group the states leading into the splitter by block
+                touched.resize(blocks.members.len(), vec![]);
+                for &target in &splitter {
+                    let row = byte * (len + 1) + target as usize;
+                    for &source in
+                        &sources[byte * len + offsets[row]..byte * len + offsets[row + 1]]
+                    {
+                        let block = blocks.block_of[source as usize];
+                        if touched[block].is_empty() {
+                            touched_blocks.push(block);
+                        }
+                        touched[block].push(source);
+                    }
+                }
+
+                for block in touched_blocks.drain(..) {
+                    let states = std::mem::take(&mut touched[block]);
+                    if states.len() == blocks.members[block].len() {
+                        continue;
+                    }
+
+                    // The split block is handled if it was pending, and
+                    // otherwise splitting by its smaller half is enough
+                    let new = blocks.split(block, &states);
+                    is_pending.push(false);
+                    let next = match is_pending[block]
+                        || blocks.members[new].len() <= blocks.members[block].len()
+                    {
+                        true => new,
+                        false => block,
+                    };
+                    is_pending[next] = true;
+                    pending.push(next);
+                }
+            }
+        }
+
+        blocks.into_table(dfa)
+    }
+
+    /// Runs the DFA over `bytes`, which end the input and follow `behind`.
+    fn search(
+        &self,
+        bytes: impl ExactSizeIterator<Item = u8>,
+        behind: Context,
+        earliest: bool,
+    ) -> Option<usize> {
+        let len = bytes.len();
+        let mut state = self.starts[behind as usize] as usize;
+        let mut last_match = None;
+
+        for (i, byte) in bytes.enumerate() {
+            if self.is_match[state] || (byte == b'\n' && self.eol_match[state]) {
+                last_match = Some(i);
+                if earliest {
+                    return last_match;
+                }
+            }
+            state = self.next[256 * state + byte as usize] as usize;
+            if state == DEAD as usize {
+                return last_match;
+            }
+        }
+
+        if self.eoi_match[state] {
+            last_match = Some(len);
+        }
+        last_match
+    }
+
+    /// Writes the number of states, the start states, a byte of flags per
+    /// state and the transitions, all integers being little-endian `u32`s.
+    fn write(&self, bytes: &mut Vec<u8>) {
+        bytes.extend((self.is_match.len() as u32).to_le_bytes());
+        for start in self.starts {
+            bytes.extend(start.to_le_bytes());
+        }
+        for state in 0..self.is_match.len() {
+            bytes.push(
+                u8::from(self.is_match[state])
+                    | u8::from(self.eoi_match[state]) << 1
+                    | u8::from(self.eol_match[state]) << 2,
+            );
+        }
+        for next in &self.next {
+            bytes.extend(next.to_le_bytes());
+        }
+    }
+
+    fn read(reader: &mut Reader) -> Result<Self, DfaError> {
+        let len = reader.u32()? as usize;
+        let starts = [reader.u32()?, reader.u32()?, reader.u32()?];
+        if len == 0 || starts.iter().any(|&start| start as usize >= len) {
+            return Err(DfaError::BadSerialization(
+                "start state out of range".into(),
+            ));
+        }
+
+        let flags = reader.take(len)?;
+        if flags.iter().any(|&flags| flags > 0b111) {
+            return Err(DfaError::BadSerialization("unknown state flags".into()));
+        }
+        let is_match = flags.iter().map(|&flags| flags & 1 != 0).collect();
+        let eoi_match = flags.iter().map(|&flags| flags & 2 != 0).collect();
+        let eol_match = flags.iter().map(|&flags| flags & 4 != 0).collect();
+
+        let table_len = len
+            .checked_mul(256 * 4)
+            .ok_or_else(|| DfaError::BadSerialization("too many states".into()))?;
+        let next: Vec<u32> = reader
+            .take(table_len)?
+            .chunks_exact(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        if next.iter().any(|&state| state as usize >= len) {
+            return Err(DfaError::BadSerialization("transition out of range".into()));
+        }
+        if next[..256].iter().any(|&state| state != DEAD) || flags[0] != 0 {
+            return Err(DfaError::BadSerialization("state 0 is not dead".into()));
+        }
+
+        Ok(Self {
+            next,
+            is_match,
+            eoi_match,
+            eol_match,
+            starts,
+        })
+    }
+}
+
+/// A partition of the states of a DFA into blocks.
+struct Blocks {
+    members: Vec<Vec<u32>>,
+    block_of: Vec<usize>,
+    /// Index of each state in the members of its block
+    index: Vec<usize>,
+}
+
+impl Blocks {
+    /// Starts with one block per kind of state, telling apart the states
+    /// that match now, those that match if the input ends and those that
+    /// match before a `\n`.
+    fn new(dfa: &Determinized) -> Self {
+        let mut blocks = Self {
+            members: vec![],
+            block_of: vec![0; dfa.next.len()],
+            index: vec![0; dfa.next.len()],
+        };
+        let mut kinds = std::collections::HashMap::new();
+
+        for state in 0..dfa.next.len() {
+            let kind = (
+                dfa.is_match[state],
+                dfa.eoi_match[state],
+                dfa.eol_match[state],
+            );
+            let block = *kinds.entry(kind).or_insert_with(|| {
+                blocks.members.push(vec![]);
+                blocks.members.len() - 1
+            });
+            blocks.block_of[state] = block;
+            blocks.index[state] = blocks.members[block].len();
+            blocks.members[block].push(state as u32);
+        }
+        blocks
+    }
+
+    /// Moves `states` out of `block` into a new block, returning it.
+    fn split(&mut self, block: usize, states: &[u32]) -> usize {
+        let new = self.members.len();
+        self.members.push(Vec::with_capacity(states.len()));
+
+        for &state in states {
+            let state = state as usize;
+            let members = &mut self.members[block];
+            let index = self.index[state];
+            members.swap_remove(index);
+            if let Some(&moved) = members.get(index) {
+                self.index[moved as usize] = index;
+            }
+
+            self.block_of[state] = new;
+            self.index[state] = self.members[new].len();
+            self.members[new].push(state as u32);
+        }
+        new
+    }
+
+    /// Builds the DFA with one state per block, the dead state's block
+    /// coming first.
+    fn into_table(self, dfa: &Determinized) -> Table {
+        let dead_block = self.block_of[DEAD as usize];
+        let mut ids = vec![0; self.members.len()];
+        let mut order = vec![dead_block];
+        order.extend((0..self.members.len()).filter(|&block| block != dead_block));
+        for (id, &block) in order.iter().enumerate() {
+            ids[block] = id as u32;
+        }
+
+        let mut table = Table {
+            next: Vec::with_capacity(256 * order.len()),
+            is_match: vec![],
+            eoi_match: vec![],
+            eol_match: vec![],
+            starts: dfa.starts.map(|start| ids[self.block_of[start as usize]]),
+        };
+        for block in order {
+            let state = self.members[block][0] as usize;
+            table.next.extend(
+                dfa.next[state]
+                    .iter()
+                    .map(|&next| ids[self.block_of[next as usize]]),
+            );
+            table.is_match.push(dfa.is_match[state]);
+            table.eoi_match.push(dfa.eoi_match[state]);
+            table.eol_match.push(dfa.eol_match[state]);
+        }
+        table
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DfaError> {
+        if self.bytes.len() < len {
+            return Err(DfaError::BadSerialization("unexpected end".into()));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, DfaError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATTERNS: [&str; 10] = [
+        "a|ab|abc",
+        "(a*)*b",
+        "(?:ab|a)*?c",
+        "(?-u:\\w+)\\s\\d{2,3}",
+        "^(?:a|b)+$",
+        "[^a]+",
+        "é+|.$",
+        "",
+        "(?m)^a+$",
+        "(?m)$\n^|b$",
+    ];
+    const HAYSTACKS: [&str; 12] = [
+        "", "abc", "aab", "aaabc", "foo 123", "ba", "abab", "aééb", "xé", "a\nb", "ba\naa\n",
+        "\nb\n",
+    ];
+
+    #[test]
+    fn test_matches_regex() {
+        for pattern in PATTERNS {
+            let regex = Regex::new(pattern).unwrap();
+            let dfa = Dfa::from_regex(&regex).unwrap();
+
+            for haystack in HAYSTACKS {
+                let expected = regex.find(haystack);
+                let found = dfa.find(haystack);
+                assert_eq!(found, expected, "{} on {:?}", pattern, haystack);
+                assert_eq!(dfa.is_match(haystack), expected.is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_minimize() {
+        let regex = Regex::new("(?:a|b)*c").unwrap();
+        let determinized = LazyDfa::new(&regex.program, Direction::Forward)
+            .determinize(STATE_LIMIT)
+            .unwrap();
+        let table = Table::minimized(&determinized);
+
+        // Dead, waiting for a `c`, and matched
+        assert!(table.is_match.len() < determinized.next.len());
+        assert_eq!(table.is_match.len(), 3);
+        assert!(table.next[..256].iter().all(|&next| next == DEAD));
+    }
+
+    #[test]
+    fn test_serialization_roundtrip() {
+        for pattern in PATTERNS {
+            let dfa = Dfa::from_regex(&Regex::new(pattern).unwrap()).unwrap();
+            assert_eq!(Dfa::from_bytes(&dfa.to_bytes()).unwrap(), dfa);
+        }
+    }
+
+    #[test]
+    fn test_corrupt_serialization() {
+        let bytes = Dfa::from_regex(&Regex::new("a+b|c").unwrap())
+            .unwrap()
+            .to_bytes();
+
+        for len in 0..bytes.len() {
+            assert!(Dfa::from_bytes(&bytes[..len]).is_err());
+        }
+        assert!(Dfa::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+
+        // Whatever gets through validation must not panic when searching
+        for i in 0..bytes.len() {
+            for flip in [0x01, 0x80, 0xFF] {
+                let mut corrupt = bytes.clone();
+                corrupt[i] ^= flip;
+                if let Ok(dfa) = Dfa::from_bytes(&corrupt) {
+                    dfa.find("xxaab c").map(|m| m.as_str());
+                    dfa.find("é").map(|m| m.as_str());
+                    dfa.is_match("é");
+                }
+            }
+        }
+
+        // Both tables accept right after a 0xC3 byte, which ends a match
+        // in the middle of `é`
+        let mut table = vec![3, 0, 0, 0];
+        table.extend([1, 0, 0, 0].repeat(3));
+        table.extend([0, 0, 0b011]);
+        let mut next = [0u32; 3 * 256];
+        next[256 + 0xC3] = 2;
+        table.extend(next.iter().flat_map(|state| state.to_le_bytes()));
+        let dfa = Dfa::from_bytes(&[MAGIC.as_slice(), &table, &table].concat()).unwrap();
+        assert_eq!(dfa.find("é").map(|m| m.as_str()), None);
+    }
+
+    #[test]
+    fn test_too_many_states() {
+        let regex = Regex::new("(?:a|b)*a(?:a|b){14}").unwrap();
+        assert!(matches!(
+            Dfa::from_regex(&regex),
+            Err(DfaError::TooManyStates(STATE_LIMIT))
+        ));
+    }
+}
@@ -2,7 +2,15 @@ use crate::tokenizer::Token;
 
 pub enum ParseError {
     UnexpectedToken(Token, String),
+    UnexpectedEnd(String),
     BadEscapeSequence(usize, String),
+    NestLimitExceeded(usize, usize),
+    BadGroupName(usize, String),
+    BadRepetition(usize, String),
+    RepetitionLimitExceeded(usize, u32),
+    BadCharRange(usize, String),
+    BadFlag(usize, String),
+    SizeLimitExceeded(usize),
 }
 
 impl std::fmt::Display for ParseError {
@@ -11,12 +19,42 @@ impl std::fmt::Display for ParseError {
             ParseError::UnexpectedToken(tok, expected) => {
                 format!("Unexpected token {:?}, expected {}", tok, expected)
             }
+            ParseError::UnexpectedEnd(expected) => {
+                format!("Unexpected end of pattern, expected {}", expected)
+            }
             ParseError::BadEscapeSequence(pos, expected) => {
                 format!(
                     "Bad escape sequence at position {}, expected {}",
                     pos, expected
                 )
             }
+            ParseError::NestLimitExceeded(pos, limit) => {
+                format!(
+                    "Group at position {} exceeds the nesting limit of {}",
+                    pos, limit
+                )
+            }
+            ParseError::BadGroupName(pos, reason) => {
+                format!("Bad group name at position {}: {}", pos, reason)
+            }
+            ParseError::BadRepetition(pos, reason) => {
+                format!("Bad repetition at position {}: {}", pos, reason)
+            }
+            ParseError::RepetitionLimitExceeded(pos, limit) => {
+                format!(
+                    "Repetition at position {} exceeds the limit of {}",
+                    pos, limit
+                )
+            }
+            ParseError::BadCharRange(pos, reason) => {
+                format!("Bad char range at position {}: {}", pos, reason)
+            }
+            ParseError::BadFlag(pos, reason) => {
+                format!("Bad flag at position {}: {}", pos, reason)
+            }
+            ParseError::SizeLimitExceeded(limit) => {
+                format!("Compiled pattern exceeds the size limit of {} bytes", limit)
+            }
         };
 
         write!(f, "{}", msg)
@@ -24,19 +62,55 @@ impl std::fmt::Display for ParseError {
 }
 
 impl std::fmt::Debug for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+pub enum DfaError {
+    TooManyStates(usize),
+    BadSerialization(String),
+}
+
+impl std::fmt::Display for DfaError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let msg = match self {
-            ParseError::UnexpectedToken(tok, expected) => {
-                format!("Unexpected token {:?}, expected {}", tok, expected)
+            DfaError::TooManyStates(limit) => {
+                format!("DFA would need more than {} states", limit)
             }
-            ParseError::BadEscapeSequence(pos, expected) => {
-                format!(
-                    "Bad escape sequence at position {}, expected {}",
-                    pos, expected
-                )
+            DfaError::BadSerialization(reason) => {
+                format!("Bad serialized DFA: {}", reason)
+            }
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+impl std::fmt::Debug for DfaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+pub enum SetError {
+    BadPattern(usize, ParseError),
+}
+
+impl std::fmt::Display for SetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let msg = match self {
+            SetError::BadPattern(index, err) => {
+                format!("Bad pattern at index {}: {}", index, err)
             }
         };
 
         write!(f, "{}", msg)
     }
 }
+
+impl std::fmt::Debug for SetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
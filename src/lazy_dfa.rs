@@ -0,0 +1,740 @@
+use std::collections::HashMap;
+
+use crate::nfa::{Inst, Look, Program, StateId};
+
+/// Memory a [`Cache`] may use by default, in bytes.
+pub const DEFAULT_CACHE_CAPACITY: usize = 2 * (1 << 20);
+
+/// A search gives up once it has cleared the cache this many times, unless
+/// each clear still let it scan enough bytes per state built.
+const MIN_CLEARS: usize = 3;
+const MIN_BYTES_PER_STATE: usize = 10;
+
+/// Index of a DFA state in a [`Cache`].
+pub type DfaId = u32;
+
+const UNKNOWN: DfaId = DfaId::MAX;
+/// The state without threads, from which nothing can match.
+pub const DEAD: DfaId = 0;
+
+/// Returned when the cache thrashes, in which case a slower engine should
+/// run the search instead.
+#[derive(Debug, PartialEq)]
+pub struct GaveUp;
+
+/// How a DFA walks the haystack and which NFA threads it keeps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    /// Runs forward and drops the threads of lower priority than a match,
+    /// finding where the leftmost-first match ends.
+    Forward,
+    /// Runs backward from the end of a match over a reverse program and
+    /// keeps every thread, finding where the longest match starts.
+    Reverse,
+}
+
+/// What lies on one side of a position, as far as assertions can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Context {
+    /// The start or end of the input
+    Text,
+    Newline,
+    Other,
+}
+
+impl Context {
+    /// The context given by the byte next to a position, if any.
+    pub fn of(byte: Option<u8>) -> Self {
+        match byte {
+            None => Context::Text,
+            Some(b'\n') => Context::Newline,
+            Some(_) => Context::Other,
+        }
+    }
+}
+
+/// A DFA state: the NFA states of the threads alive at some position, in
+/// priority order. Only consuming states, `Match` and assertions about what
+/// follows the position, which are left pending until the next byte is
+/// known, are kept.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    nfa: Box<[StateId]>,
+    /// What precedes the position, which only matters to the pending
+    /// assertions
+    behind: Context,
+}
+
+#[derive(Debug)]
+struct State {
+    key: Key,
+    is_match: bool,
+    /// Whether the input ending here would make this a match state
+    eoi_match: bool,
+    /// Whether a `\n` coming next would make this a match state
+    eol_match: bool,
+    next: Box<[DfaId; 256]>,
+}
+
+/// The DFA states built so far for one program and direction, bounded by a
+/// memory budget.
+#[derive(Debug)]
+pub struct Cache {
+    states: Vec<State>,
+    ids: HashMap<Key, DfaId>,
+    memory: usize,
+    capacity: usize,
+    clears: usize,
+    /// Bytes scanned since the last clear
+    scanned: usize,
+    stack: Vec<StateId>,
+    /// Marks the NFA states visited by a closure, reset after each one
+    seen: Vec<bool>,
+    visited: Vec<StateId>,
+}
+
+impl Cache {
+    pub fn new(capacity: usize) -> Self {
+        let mut cache = Self {
+            states: vec![],
+            ids: HashMap::new(),
+            memory: 0,
+            capacity,
+            clears: 0,
+            scanned: 0,
+            stack: vec![],
+            seen: vec![],
+            visited: vec![],
+        };
+        cache.clear();
+        cache
+    }
+
+    fn clear(&mut self) {
+        self.states.clear();
+        self.ids.clear();
+        self.memory = 0;
+        self.scanned = 0;
+
+        let dead = Key {
+            nfa: [].into(),
+            behind: Context::Other,
+        };
+        self.insert(dead, [false; 3]);
+    }
+
+    /// Adds a state with its `is_match`, `eoi_match` and `eol_match` flags.
+    fn insert(&mut self, key: Key, [is_match, eoi_match, eol_match]: [bool; 3]) -> DfaId {
+        let id = self.states.len() as DfaId;
+        self.memory += state_memory(&key);
+        self.ids.insert(key.clone(), id);
+        self.states.push(State {
+            key,
+            is_match,
+            eoi_match,
+            eol_match,
+            next: Box::new([UNKNOWN; 256]),
+        });
+        id
+    }
+}
+
+/// Approximate memory taken by a state, counting its key twice since the
+/// map holds a copy of it.
+fn state_memory(key: &Key) -> usize {
+    std::mem::size_of::<State>()
+        + std::mem::size_of::<[DfaId; 256]>()
+        + 2 * (std::mem::size_of::<Key>() + key.nfa.len() * std::mem::size_of::<StateId>())
+}
+
+/// Every state of a DFA reachable from its start states, as built by
+/// [`LazyDfa::determinize`].
+pub struct Determinized {
+    pub next: Vec<[DfaId; 256]>,
+    pub is_match: Vec<bool>,
+    pub eoi_match: Vec<bool>,
+    pub eol_match: Vec<bool>,
+    /// Start states, indexed by the [`Context`] before the search
+    pub starts: [DfaId; 3],
+}
+
+/// A DFA built lazily from an NFA program: each state and transition is
+/// computed the first time the search needs it, then cached.
+pub struct LazyDfa<'p> {
+    program: &'p Program,
+    direction: Direction,
+    anchored: bool,
+    all_matches: bool,
+}
+
+impl<'p> LazyDfa<'p> {
+    pub fn new(program: &'p Program, direction: Direction) -> Self {
+        Self {
+            program,
+            direction,
+            anchored: false,
+            all_matches: false,
+        }
+    }
+
+    /// Only finds forward matches starting at the start of the haystack.
+    pub fn anchored(mut self, anchored: bool) -> Self {
+        self.anchored = anchored;
+        self
+    }
+
+    /// Keeps the threads of lower priority than a match going forward, so
+    /// every pattern of a set gets a chance to match. States built this way
+    /// must not share a cache with states built without it.
+    pub fn all_matches(mut self, all_matches: bool) -> Self {
+        self.all_matches = all_matches;
+        self
+    }
+
+    /// Searches forward from `start`, returning the end of the
+    /// leftmost-first match. With `earliest`, stops at the first match
+    /// position seen instead, which is enough to tell whether there is one.
+    pub fn find_end(
+        &self,
+        cache: &mut Cache,
+        haystack: &[u8],
+        start: usize,
+        earliest: bool,
+    ) -> Result<Option<usize>, GaveUp> {
+        debug_assert_eq!(self.direction, Direction::Forward);
+        let behind = Context::of(start.checked_sub(1).map(|i| haystack[i]));
+        let bytes = haystack[start..].iter().copied();
+        let found = self.search(cache, bytes, behind, Context::Text, earliest)?;
+        Ok(found.map(|scanned| start + scanned))
+    }
+
+    /// Searches backward from `end` down to `min`, returning the smallest
+    /// position at which a match ending at `end` starts.
+    pub fn find_start(
+        &self,
+        cache: &mut Cache,
+        haystack: &[u8],
+        min: usize,
+        end: usize,
+    ) -> Result<Option<usize>, GaveUp> {
+        debug_assert_eq!(self.direction, Direction::Reverse);
+        // Reversed, the input starts where it used to end
+        let behind = Context::of(haystack.get(end).copied());
+        let ahead = Context::of(min.checked_sub(1).map(|i| haystack[i]));
+        let bytes = haystack[min..end].iter().rev().copied();
+        let found = self.search(cache, bytes, behind, ahead, false)?;
+        Ok(found.map(|scanned| end - scanned))
+    }
+
+    /// Scans the whole haystack forward, setting `matched[i]` if pattern `i`
+    /// of a set matches somewhere. Stops early once every pattern matched.
+    pub fn which_matches(
+        &self,
+        cache: &mut Cache,
+        haystack: &[u8],
+        matched: &mut [bool],
+    ) -> Result<(), GaveUp> {
+        debug_assert!(self.direction == Direction::Forward && self.all_matches);
+        cache.clears = 0;
+        cache.scanned = 0;
+
+        let mut left = matched.iter().filter(|&&m| !m).count();
+        let mut id = self.start_state(cache, self.program.start_unanchored, Context::Text)?;
+        for &byte in haystack {
+            let state = &cache.states[id as usize];
+            if state.is_match {
+                left -= self.record(&state.key.nfa, matched);
+            }
+            // Other patterns may be waiting on an assertion that holds
+            // before a `\n`
+            if byte == b'\n' && state.eol_match {
+                let key = state.key.clone();
+                let at_eol = self.closure_with(cache, &key.nfa, key.behind, Some(Context::Newline));
+                left -= self.record(&at_eol, matched);
+            }
+            if left == 0 {
+                return Ok(());
+            }
+
+            id = match cache.states[id as usize].next[byte as usize] {
+                UNKNOWN => self.next_state(cache, id, byte)?,
+                next => next,
+            };
+            cache.scanned += 1;
+        }
+
+        // Assertions about the end of the input hold now
+        let key = cache.states[id as usize].key.clone();
+        if cache.states[id as usize].eoi_match {
+            let at_end = self.closure_with(cache, &key.nfa, key.behind, Some(Context::Text));
+            self.record(&at_end, matched);
+        }
+        Ok(())
+    }
+
+    /// Sets `matched` for the patterns whose `Match` state is among `nfa`,
+    /// returning how many were not set before.
+    fn record(&self, nfa: &[StateId], matched: &mut [bool]) -> usize {
+        nfa.iter()
+            .filter_map(|&id| self.program.pattern_of(id))
+            .filter(|&pattern| !std::mem::replace(&mut matched[pattern], true))
+            .count()
+    }
+
+    /// Builds every state reachable from the start states, or returns `None`
+    /// if there are more than `max_states` of them.
+    pub fn determinize(&self, max_states: usize) -> Option<Determinized> {
+        let mut cache = Cache::new(usize::MAX);
+        let start = match self.direction {
+            Direction::Forward => self.program.start_unanchored,
+            Direction::Reverse => self.program.start,
+        };
+        let starts = [
+            self.start_state(&mut cache, start, Context::Text).ok()?,
+            self.start_state(&mut cache, start, Context::Newline).ok()?,
+            self.start_state(&mut cache, start, Context::Other).ok()?,
+        ];
+
+        // Bytes no instruction tells apart always lead to the same state,
+        // so one of each class is enough
+        let mut class_starts = [false; 256];
+        class_starts[0] = true;
+        // Line assertions also tell `\n` apart
+        class_starts[b'\n' as usize] = true;
+        class_starts[b'\n' as usize + 1] = true;
+        for inst in &self.program.insts {
+            let ranges = match inst {
+                Inst::Char { byte, .. } => vec![(*byte, *byte)],
+                Inst::Class { ranges } => {
+                    ranges.iter().map(|&(start, end, _)| (start, end)).collect()
+                }
+                _ => continue,
+            };
+            for (start, end) in ranges {
+                class_starts[start as usize] = true;
+                if end < 255 {
+                    class_starts[end as usize + 1] = true;
+                }
+            }
+        }
+
+        let mut id = 0;
+        while id < cache.states.len() {
+            if cache.states.len() > max_states {
+                return None;
+            }
+            let mut next = DEAD;
+            for byte in 0..=255 {
+                if class_starts[byte as usize] {
+                    next = match cache.states[id].next[byte as usize] {
+                        UNKNOWN => self.next_state(&mut cache, id as DfaId, byte).ok()?,
+                        known => known,
+                    };
+                }
+                cache.states[id].next[byte as usize] = next;
+            }
+            id += 1;
+        }
+
+        let is_match = cache.states.iter().map(|state| state.is_match).collect();
+        let eoi_match = cache.states.iter().map(|state| state.eoi_match).collect();
+        let eol_match = cache.states.iter().map(|state| state.eol_match).collect();
+        Some(Determinized {
+            next: cache.states.into_iter().map(|state| *state.next).collect(),
+            is_match,
+            eoi_match,
+            eol_match,
+            starts,
+        })
+    }
+
+    /// Runs the DFA over `bytes`, returning how many bytes were scanned when
+    /// the last match, or the first one with `earliest`, was seen. `behind`
+    /// and `ahead` tell what comes right before and after `bytes`.
+    fn search(
+        &self,
+        cache: &mut Cache,
+        bytes: impl ExactSizeIterator<Item = u8>,
+        behind: Context,
+        ahead: Context,
+        earliest: bool,
+    ) -> Result<Option<usize>, GaveUp> {
+        let len = bytes.len();
+        cache.clears = 0;
+        cache.scanned = 0;
+
+        let start = match self.direction {
+            Direction::Forward if !self.anchored => self.program.start_unanchored,
+            Direction::Forward | Direction::Reverse => self.program.start,
+        };
+        let mut id = self.start_state(cache, start, behind)?;
+        let mut last_match = None;
+
+        for (i, byte) in bytes.enumerate() {
+            let state = &cache.states[id as usize];
+            if state.is_match || (byte == b'\n' && state.eol_match) {
+                last_match = Some(i);
+                if earliest {
+                    return Ok(last_match);
+                }
+            }
+
+            id = match cache.states[id as usize].next[byte as usize] {
+                UNKNOWN => self.next_state(cache, id, byte)?,
+                next => next,
+            };
+            cache.scanned += 1;
+            if id == DEAD {
+                return Ok(last_match);
+            }
+        }
+
+        let state = &cache.states[id as usize];
+        let matched = match ahead {
+            Context::Text => state.eoi_match,
+            Context::Newline => state.eol_match,
+            Context::Other => state.is_match,
+        };
+        if matched {
+            last_match = Some(len);
+        }
+        Ok(last_match)
+    }
+
+    fn start_state(
+        &self,
+        cache: &mut Cache,
+        start: StateId,
+        behind: Context,
+    ) -> Result<DfaId, GaveUp> {
+        let nfa = self.closure(cache, &[start], behind);
+        self.add_state(cache, Key { nfa, behind }, None)
+    }
+
+    /// Computes the transition of `id` on `byte` and caches it.
+    fn next_state(&self, cache: &mut Cache, id: DfaId, byte: u8) -> Result<DfaId, GaveUp> {
+        let key = cache.states[id as usize].key.clone();
+        // Assertions about the end of a line hold right before a `\n`, and
+        // the pending ones fail before any other byte
+        let nfa = match byte {
+            b'\n' => self.closure_with(cache, &key.nfa, key.behind, Some(Context::Newline)),
+            _ => key.nfa,
+        };
+
+        let mut seeds = vec![];
+        for &nfa_id in nfa.iter() {
+            match &self.program.insts[nfa_id] {
+                Inst::Char { byte: b, next } if *b == byte => seeds.push(*next),
+                Inst::Class { ranges } => {
+                    if let Some(&(_, _, next)) = ranges
+                        .iter()
+                        .find(|&&(start, end, _)| start <= byte && byte <= end)
+                    {
+                        seeds.push(next);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let behind = Context::of(Some(byte));
+        let nfa = self.closure(cache, &seeds, behind);
+        self.add_state(cache, Key { nfa, behind }, Some((id, byte)))
+    }
+
+    /// Returns the state for `key`, building it if needed, and records it
+    /// as the transition `from` another state on a byte.
+    fn add_state(
+        &self,
+        cache: &mut Cache,
+        key: Key,
+        from: Option<(DfaId, u8)>,
+    ) -> Result<DfaId, GaveUp> {
+        let next = match cache.ids.get(&key) {
+            Some(&next) => next,
+            None => {
+                if cache.memory + state_memory(&key) > cache.capacity {
+                    self.make_room(cache)?;
+                    // The state we came from is gone, so its transition
+                    // cannot be recorded
+                    let flags = self.match_flags(cache, &key);
+                    return Ok(cache.insert(key, flags));
+                }
+                let flags = self.match_flags(cache, &key);
+                cache.insert(key, flags)
+            }
+        };
+
+        if let Some((from, byte)) = from {
+            cache.states[from as usize].next[byte as usize] = next;
+        }
+        Ok(next)
+    }
+
+    /// Clears the cache, or gives up if it keeps filling up without the
+    /// search making enough progress.
+    fn make_room(&self, cache: &mut Cache) -> Result<(), GaveUp> {
+        let states = cache.states.len();
+        if cache.clears >= MIN_CLEARS && cache.scanned < MIN_BYTES_PER_STATE * states {
+            return Err(GaveUp);
+        }
+
+        cache.clears += 1;
+        cache.clear();
+        Ok(())
+    }
+
+    /// Returns whether `key` is a match state as is, at the end of the
+    /// input and right before a `\n`.
+    fn match_flags(&self, cache: &mut Cache, key: &Key) -> [bool; 3] {
+        let has_match =
+            |nfa: &[StateId]| nfa.iter().any(|&id| self.program.insts[id] == Inst::Match);
+        let is_match = has_match(&key.nfa);
+        let pending: Vec<_> = key
+            .nfa
+            .iter()
+            .copied()
+            .filter(|&id| matches!(self.program.insts[id], Inst::Assert { .. }))
+            .collect();
+        if pending.is_empty() {
+            return [is_match; 3];
+        }
+
+        // Even a match state can have threads of other patterns waiting
+        let at_end = self.closure_with(cache, &pending, key.behind, Some(Context::Text));
+        let at_eol = self.closure_with(cache, &pending, key.behind, Some(Context::Newline));
+        [
+            is_match,
+            is_match || has_match(&at_end),
+            is_match || has_match(&at_eol),
+        ]
+    }
+
+    fn closure(&self, cache: &mut Cache, seeds: &[StateId], behind: Context) -> Box<[StateId]> {
+        self.closure_with(cache, seeds, behind, None)
+    }
+
+    /// Follows every transition that consumes no input from `seeds`, in
+    /// priority order, keeping the states where threads stop. Assertions
+    /// about what follows are checked against `ahead`, or kept pending
+    /// when it is unknown.
+    fn closure_with(
+        &self,
+        cache: &mut Cache,
+        seeds: &[StateId],
+        behind: Context,
+        ahead: Option<Context>,
+    ) -> Box<[StateId]> {
+        let insts = &self.program.insts;
+        let mut set = vec![];
+        cache.seen.resize(insts.len(), false);
+
+        'seeds: for &seed in seeds {
+            cache.stack.push(seed);
+            while let Some(id) = cache.stack.pop() {
+                if std::mem::replace(&mut cache.seen[id], true) {
+                    continue;
+                }
+                cache.visited.push(id);
+
+                match insts[id] {
+                    Inst::Split { first, second } => {
+                        cache.stack.push(second);
+                        cache.stack.push(first);
+                    }
+                    Inst::Jump(next) | Inst::Save { next, .. } => cache.stack.push(next),
+                    Inst::Assert { look, next } => {
+                        let holds = match (look, ahead) {
+                            (Look::StartText, _) => Some(behind == Context::Text),
+                            (Look::StartLine, _) => Some(behind != Context::Other),
+                            (Look::EndText | Look::EndLine, None) => None,
+                            (Look::EndText, Some(ahead)) => Some(ahead == Context::Text),
+                            (Look::EndLine, Some(ahead)) => Some(ahead != Context::Other),
+                        };
+                        match holds {
+                            Some(true) => cache.stack.push(next),
+                            Some(false) => {}
+                            None => set.push(id),
+                        }
+                    }
+                    Inst::Match => {
+                        set.push(id);
+                        // Leftmost-first: threads of lower priority than a
+                        // match can never win
+                        if self.direction == Direction::Forward && !self.all_matches {
+                            cache.stack.clear();
+                            break 'seeds;
+                        }
+                    }
+                    Inst::Char { .. } | Inst::Class { .. } => set.push(id),
+                }
+            }
+        }
+
+        for id in cache.visited.drain(..) {
+            cache.seen[id] = false;
+        }
+        set.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nfa::DEFAULT_SIZE_LIMIT;
+    use crate::parser::{parse, Parser};
+    use crate::pikevm::PikeVm;
+    use crate::tokenizer::tokenize;
+
+    fn programs(pattern: &str) -> (Program, Program) {
+        let ast = parse(tokenize(pattern.into()).unwrap()).unwrap();
+        (
+            Program::compile(&ast, DEFAULT_SIZE_LIMIT).unwrap(),
+            Program::compile_reverse(&ast, DEFAULT_SIZE_LIMIT).unwrap(),
+        )
+    }
+
+    fn find(pattern: &str, haystack: &str) -> Option<(usize, usize)> {
+        let (forward, reverse) = programs(pattern);
+        let mut cache = Cache::new(DEFAULT_CACHE_CAPACITY);
+        let end = LazyDfa::new(&forward, Direction::Forward)
+            .find_end(&mut cache, haystack.as_bytes(), 0, false)
+            .unwrap()?;
+
+        let mut cache = Cache::new(DEFAULT_CACHE_CAPACITY);
+        let start = LazyDfa::new(&reverse, Direction::Reverse)
+            .find_start(&mut cache, haystack.as_bytes(), 0, end)
+            .unwrap()
+            .unwrap();
+        Some((start, end))
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("abc", "xxabcabc"), Some((2, 5)));
+        assert_eq!(find("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(find("b|ab", "ab"), Some((0, 2)));
+        assert_eq!(find("a+?", "baaa"), Some((1, 2)));
+        assert_eq!(find("a*", "baa"), Some((0, 0)));
+        assert_eq!(find("^b", "ab"), None);
+        assert_eq!(find("b$", "abb"), Some((2, 3)));
+        assert_eq!(find("$", "ab"), Some((2, 2)));
+        assert_eq!(find("^$", ""), Some((0, 0)));
+        assert_eq!(find("[é-ê]+", "aéêe"), Some((1, 5)));
+        assert_eq!(find("x", "ab"), None);
+        assert_eq!(find("(?m)^b$", "a\nb\nc"), Some((2, 3)));
+        assert_eq!(find("(?m)a$", "ab\na"), Some((3, 4)));
+        assert_eq!(find("(?m)^$", "a\n\nb"), Some((2, 2)));
+        assert_eq!(find("^b", "a\nb"), None);
+        assert_eq!(find("(?-s)a.b", "a\nb"), None);
+    }
+
+    #[test]
+    fn test_matches_pikevm() {
+        let patterns = [
+            "a|ab|abc",
+            "(a*)*b",
+            "(?:ab|a)*?c",
+            "\\w+\\s\\d{2,3}",
+            "^(?:a|b)+$",
+            "[^a]+",
+            "é+|.$",
+            "(?m)^a+$",
+            "(?m)$\n^|b$",
+            "(?ms).+^b",
+            "a.*",
+        ];
+        let haystacks = [
+            "", "abc", "aab", "aaabc", "foo 123", "ba", "abab", "aééb", "xé", "\n", "a\nb",
+            "ba\naa\n", "\nb\n",
+        ];
+
+        for pattern in patterns {
+            let (forward, _) = programs(pattern);
+            for haystack in haystacks {
+                let mut slots = [None, None];
+                let expected = PikeVm::new(&forward, 2)
+                    .search(haystack.as_bytes(), 0, true, &mut slots)
+                    .then(|| (slots[0].unwrap(), slots[1].unwrap()));
+                assert_eq!(
+                    find(pattern, haystack),
+                    expected,
+                    "{} on {:?}",
+                    pattern,
+                    haystack
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_earliest() {
+        let (forward, _) = programs("a+");
+        let mut cache = Cache::new(DEFAULT_CACHE_CAPACITY);
+        let dfa = LazyDfa::new(&forward, Direction::Forward);
+
+        assert_eq!(dfa.find_end(&mut cache, b"baaa", 0, true), Ok(Some(2)));
+        assert_eq!(dfa.find_end(&mut cache, b"baaa", 0, false), Ok(Some(4)));
+    }
+
+    #[test]
+    fn test_find_start_from_min() {
+        let mut cache = Cache::new(DEFAULT_CACHE_CAPACITY);
+        let (_, reverse) = programs("a+");
+        let dfa = LazyDfa::new(&reverse, Direction::Reverse);
+        assert_eq!(dfa.find_start(&mut cache, b"aaa", 1, 3), Ok(Some(1)));
+
+        // The search stops short of the start of the input
+        let mut cache = Cache::new(DEFAULT_CACHE_CAPACITY);
+        let (_, reverse) = programs("^a");
+        let dfa = LazyDfa::new(&reverse, Direction::Reverse);
+        assert_eq!(dfa.find_start(&mut cache, b"aa", 1, 2), Ok(None));
+        assert_eq!(dfa.find_start(&mut cache, b"aa", 0, 1), Ok(Some(0)));
+    }
+
+    #[test]
+    fn test_anchored() {
+        let (forward, _) = programs("a+");
+        let mut cache = Cache::new(DEFAULT_CACHE_CAPACITY);
+        let dfa = LazyDfa::new(&forward, Direction::Forward).anchored(true);
+
+        assert_eq!(dfa.find_end(&mut cache, b"aab", 0, false), Ok(Some(2)));
+        assert_eq!(dfa.find_end(&mut cache, b"baaa", 0, false), Ok(None));
+    }
+
+    #[test]
+    fn test_gives_up_when_thrashing() {
+        // Needs a state per distinct window of the last 8 bytes
+        let tokens = tokenize("(?-u)[ab]*a[ab]{7}".into()).unwrap();
+        let ast = Parser::new(&tokens)
+            .allow_invalid_utf8(true)
+            .parse()
+            .unwrap();
+        let program = Program::compile(&ast, DEFAULT_SIZE_LIMIT).unwrap();
+        let dfa = LazyDfa::new(&program, Direction::Forward);
+
+        let mut state = 1u32;
+        let haystack: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                b'a' + (state & 1) as u8
+            })
+            .collect();
+
+        let mut cache = Cache::new(
+            32 * state_memory(&Key {
+                nfa: [].into(),
+                behind: Context::Other,
+            }),
+        );
+        assert_eq!(dfa.find_end(&mut cache, &haystack, 0, false), Err(GaveUp));
+
+        let mut cache = Cache::new(DEFAULT_CACHE_CAPACITY);
+        assert!(dfa
+            .find_end(&mut cache, &haystack, 0, false)
+            .unwrap()
+            .is_some());
+    }
+}
@@ -1,166 +1,1004 @@
-//! TODO : non-capturing groups (?:abc)*
-//!
-//! TODO : capturing groups (abc) - started
-//!
-//! TODO : add support for "abc*d(efg)+" parens
-//!
-//! TODO : later support captures
-
+mod builder;
+pub mod bytes;
+mod class;
 pub mod colorize;
+pub mod dfa;
 pub mod errors;
+mod lazy_dfa;
+pub mod literal;
+mod nfa;
 mod parser;
+mod pikevm;
 mod regex;
+mod set;
+mod strategy;
+mod template;
 mod tokenizer;
+mod unicode_tables;
+mod utf8;
 
+pub use builder::RegexBuilder;
+pub use regex::Captures;
 pub use regex::Match;
+pub use regex::Matches;
 pub use regex::Regex;
+pub use regex::Split;
+pub use set::RegexSet;
+pub use set::SetMatches;
+pub use strategy::EngineInfo;
+pub use template::NoExpand;
+pub use template::Replacer;
+pub use template::Template;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::borrow::Cow;
 
     #[test]
     fn test_from_readme() {
         let regex = Regex::new("^[hH]ello,? [wW]orld ?!").unwrap();
-        let res = regex.find("Hello, world!");
-        assert!(res.is_match());
+        assert!(regex.is_match("Hello, world!"));
+
+        let m = regex.find("Hello, world!").unwrap();
+        assert_eq!((m.as_str(), m.range()), ("Hello, world!", 0..13));
     }
 
     #[test]
     fn test_no_start_anchor() {
         let reg = Regex::new(r#"hello"#).unwrap();
 
-        assert!(reg.find("hello").is_match());
-        assert!(reg.find("hello ").is_match());
-        assert!(reg.find("hello world").is_match());
-        assert!(reg.find("  hellow").is_match());
+        assert!(reg.find("hello").is_some());
+        assert!(reg.find("hello ").is_some());
+        assert!(reg.find("hello world").is_some());
+        assert!(reg.find("  hellow").is_some());
 
-        assert!(!reg.find("hell").is_match());
+        assert!(reg.find("hell").is_none());
     }
 
     #[test]
     fn test_start_anchor() {
         let reg = Regex::new(r#"^hello"#).unwrap();
 
-        assert!(reg.find("hello").is_match());
-        assert!(!reg.find("qhello").is_match());
+        assert!(reg.find("hello").is_some());
+        assert!(reg.find("qhello").is_none());
     }
 
     #[test]
     fn test_end_anchor() {
         let reg = Regex::new(r#"hello$"#).unwrap();
 
-        assert!(reg.find("hello").is_match());
-        assert!(!reg.find("helloq").is_match());
+        assert!(reg.find("hello").is_some());
+        assert!(reg.find("helloq").is_none());
     }
 
     #[test]
     fn test_any() {
         let reg = Regex::new(r#"he*llo"#).unwrap();
 
-        assert!(reg.find("hello").is_match());
-        assert!(reg.find("heeello").is_match());
-        assert!(reg.find("hllo").is_match());
+        assert!(reg.find("hello").is_some());
+        assert!(reg.find("heeello").is_some());
+        assert!(reg.find("hllo").is_some());
     }
 
     #[test]
     fn test_many() {
         let reg = Regex::new(r#"he+llo"#).unwrap();
 
-        assert!(reg.find("hello").is_match());
-        assert!(reg.find("heeello").is_match());
+        assert!(reg.find("hello").is_some());
+        assert!(reg.find("heeello").is_some());
 
-        assert!(!reg.find("hllo").is_match());
+        assert!(reg.find("hllo").is_none());
     }
 
     #[test]
     fn test_maybe() {
         let reg = Regex::new(r#"he?llo"#).unwrap();
 
-        assert!(reg.find("hello").is_match());
-        assert!(reg.find("hllo").is_match());
+        assert!(reg.find("hello").is_some());
+        assert!(reg.find("hllo").is_some());
 
-        assert!(!reg.find("llo").is_match());
-        assert!(!reg.find("hlo").is_match());
-        assert!(!reg.find("heeello").is_match());
+        assert!(reg.find("llo").is_none());
+        assert!(reg.find("hlo").is_none());
+        assert!(reg.find("heeello").is_none());
     }
 
     #[test]
     fn test_dot() {
         let reg = Regex::new(r#"hel."#).unwrap();
 
-        assert!(reg.find("helo").is_match());
-        assert!(reg.find("help").is_match());
-        assert!(reg.find("hel.").is_match());
-        assert!(reg.find("hel ").is_match());
+        assert!(reg.find("helo").is_some());
+        assert!(reg.find("help").is_some());
+        assert!(reg.find("hel.").is_some());
+        assert!(reg.find("hel ").is_some());
+    }
+
+    #[test]
+    fn test_dot_new_line() {
+        let reg = Regex::new(r#"hel.*"#).unwrap();
+        assert!(reg.find("hel\n").is_some());
+        assert_eq!(reg.find("help\nme").unwrap().as_str(), "help");
+        assert!(Regex::new(r#"hel."#).unwrap().find("hel\n").is_none());
+
+        let reg = Regex::new(r#"(?s)hel.*"#).unwrap();
+        assert_eq!(reg.find("help\nme").unwrap().as_str(), "help\nme");
     }
 
     #[test]
     fn test_dot_any() {
         let reg = Regex::new(r#"hel.*"#).unwrap();
 
-        assert!(reg.find("heloooo").is_match());
-        assert!(reg.find("helpsdf").is_match());
-        assert!(reg.find("hel.q").is_match());
-        assert!(reg.find("hel sdf").is_match());
+        assert!(reg.find("heloooo").is_some());
+        assert!(reg.find("helpsdf").is_some());
+        assert!(reg.find("hel.q").is_some());
+        assert!(reg.find("hel sdf").is_some());
     }
 
     #[test]
     fn test_dot_many() {
         let reg = Regex::new(r#"hel.+"#).unwrap();
 
-        assert!(reg.find("heloooo").is_match());
-        assert!(reg.find("helpsdf").is_match());
-        assert!(reg.find("hel.q").is_match());
+        assert!(reg.find("heloooo").is_some());
+        assert!(reg.find("helpsdf").is_some());
+        assert!(reg.find("hel.q").is_some());
 
-        assert!(!reg.find("hel").is_match());
+        assert!(reg.find("hel").is_none());
     }
 
     #[test]
     fn test_dot_maybe() {
         let reg = Regex::new(r#"hel.?"#).unwrap();
 
-        assert!(reg.find("heloooo").is_match());
-        assert!(reg.find("helpsdf").is_match());
-        assert!(reg.find("hel.q").is_match());
-        assert!(reg.find("hel").is_match());
+        assert!(reg.find("heloooo").is_some());
+        assert!(reg.find("helpsdf").is_some());
+        assert!(reg.find("hel.q").is_some());
+        assert!(reg.find("hel").is_some());
     }
 
     #[test]
     fn test_char_class() {
         let reg = Regex::new(r#"[abc]"#).unwrap();
 
-        assert!(reg.find("a").is_match());
-        assert!(reg.find("b").is_match());
-        assert!(reg.find("c").is_match());
+        assert!(reg.find("a").is_some());
+        assert!(reg.find("b").is_some());
+        assert!(reg.find("c").is_some());
 
-        assert!(!reg.find("d").is_match());
-        assert!(!reg.find("de").is_match());
+        assert!(reg.find("d").is_none());
+        assert!(reg.find("de").is_none());
     }
 
     #[test]
     fn test_char_class_any() {
         let reg = Regex::new(r#"[abc]*"#).unwrap();
 
-        assert!(reg.find("a").is_match());
-        assert!(reg.find("b").is_match());
-        assert!(reg.find("c").is_match());
-        assert!(reg.find("abc").is_match());
-        assert!(reg.find("abccba").is_match());
+        assert!(reg.find("a").is_some());
+        assert!(reg.find("b").is_some());
+        assert!(reg.find("c").is_some());
+        assert!(reg.find("abc").is_some());
+        assert!(reg.find("abccba").is_some());
 
-        assert!(reg.find("d").is_match());
-        assert!(reg.find("de").is_match());
+        assert!(reg.find("d").is_some());
+        assert!(reg.find("de").is_some());
     }
 
     #[test]
     fn test_char_class_start_anchor() {
         let reg = Regex::new(r#"^[abc]"#).unwrap();
 
-        assert!(reg.find("a").is_match());
-        assert!(reg.find("b").is_match());
-        assert!(reg.find("c").is_match());
+        assert!(reg.find("a").is_some());
+        assert!(reg.find("b").is_some());
+        assert!(reg.find("c").is_some());
+
+        assert!(reg.find("d").is_none());
+        assert!(reg.find(" a").is_none());
+    }
+
+    #[test]
+    fn test_alternation() {
+        let reg = Regex::new(r#"cat|dog"#).unwrap();
+
+        assert!(reg.find("cat").is_some());
+        assert!(reg.find("dog").is_some());
+        assert!(reg.find("hotdog").is_some());
+
+        assert_eq!(reg.find("cat|dog").map(|m| m.as_str()), Some("cat"));
+        assert!(reg.find("cow").is_none());
+    }
+
+    #[test]
+    fn test_alternation_leftmost_first() {
+        let reg = Regex::new(r#"ab|abc"#).unwrap();
+        assert_eq!(reg.find("abc").map(|m| m.as_str()), Some("ab"));
+
+        let reg = Regex::new(r#"b|ab"#).unwrap();
+        assert_eq!(reg.find("xab").unwrap().range(), 1..3);
+    }
+
+    #[test]
+    fn test_alternation_empty_branch() {
+        let reg = Regex::new(r#"a|"#).unwrap();
+
+        assert_eq!(reg.find("a").map(|m| m.as_str()), Some("a"));
+        assert_eq!(reg.find("b").map(|m| m.as_str()), Some(""));
+    }
+
+    #[test]
+    fn test_alternation_anchors() {
+        let reg = Regex::new(r#"^a|b$"#).unwrap();
+
+        assert!(reg.find("ax").is_some());
+        assert!(reg.find("xb").is_some());
+
+        assert!(reg.find("xa").is_none());
+        assert!(reg.find("bx").is_none());
+    }
+
+    #[test]
+    fn test_captures() {
+        let reg = Regex::new(r#"([abc]+)@(ex)ample"#).unwrap();
+        let caps = reg.captures("mail abc@example").unwrap();
+
+        assert_eq!(caps.len(), 3);
+        assert_eq!(caps.get(0).unwrap().as_str(), "abc@example");
+        assert_eq!(caps.get(1).unwrap().as_str(), "abc");
+        assert_eq!(caps.get(2).unwrap().range(), 9..11);
+        assert!(caps.get(3).is_none());
+
+        assert!(reg.captures("mail d@example").is_none());
+    }
+
+    #[test]
+    fn test_captures_group_zero_is_find() {
+        let reg = Regex::new(r#"h(e+)llo"#).unwrap();
+        let caps = reg.captures("say heeello").unwrap();
+
+        assert_eq!(
+            caps.get(0).unwrap().range(),
+            reg.find("say heeello").unwrap().range()
+        );
+        assert_eq!(caps.get(1).unwrap().as_str(), "eee");
+    }
+
+    #[test]
+    fn test_captures_nested() {
+        let reg = Regex::new(r#"(a(b)c)(d)"#).unwrap();
+        let caps = reg.captures("abcd").unwrap();
+
+        let groups: Vec<_> = caps.iter().map(|m| m.unwrap().as_str()).collect();
+        assert_eq!(groups, vec!["abcd", "abc", "b", "d"]);
+    }
+
+    #[test]
+    fn test_captures_non_participating() {
+        let reg = Regex::new(r#"(a)|(b)"#).unwrap();
+        let caps = reg.captures("b").unwrap();
+
+        assert!(caps.get(1).is_none());
+        assert_eq!(caps.get(2).unwrap().as_str(), "b");
+
+        let reg = Regex::new(r#"x(y)?z"#).unwrap();
+        let caps = reg.captures("xz").unwrap();
+        assert_eq!(caps.len(), 2);
+        assert!(caps.get(1).is_none());
+    }
+
+    #[test]
+    fn test_captures_repeated_group() {
+        // A repeated group reports its last iteration
+        let reg = Regex::new(r#"(ab)+"#).unwrap();
+        let caps = reg.captures("ababab").unwrap();
+
+        assert_eq!(caps.get(0).unwrap().as_str(), "ababab");
+        assert_eq!(caps.get(1).unwrap().range(), 4..6);
+    }
+
+    #[test]
+    fn test_failed_branch_does_not_leak_captures() {
+        let reg = Regex::new(r#"(a)x|ay"#).unwrap();
+        let caps = reg.captures("ay").unwrap();
+
+        assert!(caps.get(1).is_none());
+    }
+
+    #[test]
+    fn test_named_captures() {
+        let reg = Regex::new(r#"(?P<key>[abc]+)=(?<value>[xyz]+)(;)?"#).unwrap();
+        let caps = reg.captures("ab=xz;").unwrap();
+
+        assert_eq!(caps.name("key").unwrap().as_str(), "ab");
+        assert_eq!(caps.name("value").unwrap().as_str(), "xz");
+        assert!(caps.name("missing").is_none());
+
+        // Named groups are numbered like unnamed ones
+        assert_eq!(caps.get(1).unwrap().as_str(), "ab");
+        assert_eq!(caps.get(3).unwrap().as_str(), ";");
+    }
+
+    #[test]
+    fn test_capture_names() {
+        let reg = Regex::new(r#"(?P<a>x)(y)(?<b>z)"#).unwrap();
+        let names: Vec<_> = reg.capture_names().collect();
+
+        assert_eq!(names, vec![None, Some("a"), None, Some("b")]);
+    }
+
+    #[test]
+    fn test_bad_capture_names() {
+        assert!(matches!(
+            Regex::new(r#"(?P<a>x)(?P<a>y)"#),
+            Err(errors::ParseError::BadGroupName(..))
+        ));
+        assert!(matches!(
+            Regex::new(r#"(?P<a b>x)"#),
+            Err(errors::ParseError::BadGroupName(..))
+        ));
+    }
+
+    #[test]
+    fn test_non_capturing_groups() {
+        let reg = Regex::new(r#"(?:ab)+(c)"#).unwrap();
+        let caps = reg.captures("xababc").unwrap();
+
+        assert_eq!(caps.len(), 2);
+        assert_eq!(caps.get(0).unwrap().as_str(), "ababc");
+        assert_eq!(caps.get(1).unwrap().as_str(), "c");
+
+        let reg = Regex::new(r#"^(?:cat|dog)s?$"#).unwrap();
+        assert!(reg.find("cats").is_some());
+        assert!(reg.find("dog").is_some());
+        assert!(reg.find("cow").is_none());
+    }
+
+    #[test]
+    fn test_counted_repetition() {
+        let digit = "[0123456789]";
+        let reg = Regex::new(format!("{digit}{{4}}-{digit}{{2}}-{digit}{{2}}")).unwrap();
+
+        assert_eq!(
+            reg.find("released on 2024-03-15.").map(|m| m.as_str()),
+            Some("2024-03-15")
+        );
+        assert!(reg.find("2024-3-15").is_none());
+    }
+
+    #[test]
+    fn test_counted_repetition_bounds() {
+        let reg = Regex::new(r#"^a{2,3}$"#).unwrap();
+        assert!(reg.find("a").is_none());
+        assert!(reg.find("aa").is_some());
+        assert!(reg.find("aaa").is_some());
+        assert!(reg.find("aaaa").is_none());
+
+        let reg = Regex::new(r#"^(?:ab){2,}$"#).unwrap();
+        assert!(reg.find("ab").is_none());
+        assert!(reg.find("ababab").is_some());
+
+        let reg = Regex::new(r#"ba{0}"#).unwrap();
+        assert_eq!(reg.find("baa").map(|m| m.as_str()), Some("b"));
+    }
+
+    #[test]
+    fn test_counted_repetition_errors() {
+        assert!(matches!(
+            Regex::new(r#"a{3,1}"#),
+            Err(errors::ParseError::BadRepetition(..))
+        ));
+        assert!(matches!(
+            Regex::new(r#"a{5000}"#),
+            Err(errors::ParseError::RepetitionLimitExceeded(..))
+        ));
+
+        // Braces that are not a repetition keep matching literally
+        let reg = Regex::new(r#"a{b}"#).unwrap();
+        assert!(reg.find("a{b}").is_some());
+    }
+
+    #[test]
+    fn test_lazy_quantifiers() {
+        let reg = Regex::new(r#"<.+?>"#).unwrap();
+        assert_eq!(
+            reg.find("<b>bold</b> text").map(|m| m.as_str()),
+            Some("<b>")
+        );
+
+        let reg = Regex::new(r#"a*?"#).unwrap();
+        assert_eq!(reg.find("aaa").map(|m| m.as_str()), Some(""));
+
+        let reg = Regex::new(r#"a??b"#).unwrap();
+        assert_eq!(reg.find("ab").map(|m| m.as_str()), Some("ab"));
+
+        let reg = Regex::new(r#"a{2,4}?"#).unwrap();
+        assert_eq!(reg.find("aaaa").map(|m| m.as_str()), Some("aa"));
+
+        let reg = Regex::new(r#"a{2,4}?$"#).unwrap();
+        assert_eq!(reg.find("aaaa").map(|m| m.as_str()), Some("aaaa"));
+    }
+
+    #[test]
+    fn test_lazy_quantifier_in_group() {
+        let reg = Regex::new(r#"(?P<key>.+?)=(.*)"#).unwrap();
+        let caps = reg.captures("a=b=c").unwrap();
+
+        assert_eq!(caps.name("key").unwrap().as_str(), "a");
+        assert_eq!(caps.get(2).unwrap().as_str(), "b=c");
+    }
+
+    #[test]
+    fn test_quantifiers_give_back() {
+        let reg = Regex::new(r#"a*a"#).unwrap();
+        assert_eq!(reg.find("aaa").map(|m| m.as_str()), Some("aaa"));
+
+        let reg = Regex::new(r#".*foo"#).unwrap();
+        assert_eq!(reg.find("xfoofoo!").map(|m| m.as_str()), Some("xfoofoo"));
+
+        let reg = Regex::new(r#"[ab]*b"#).unwrap();
+        assert_eq!(reg.find("abab").map(|m| m.as_str()), Some("abab"));
+
+        let reg = Regex::new(r#"<.+>"#).unwrap();
+        assert_eq!(
+            reg.find("<b>bold</b>").map(|m| m.as_str()),
+            Some("<b>bold</b>")
+        );
+
+        let reg = Regex::new(r#"^a{2,4}a{2}$"#).unwrap();
+        assert!(reg.find("aaaa").is_some());
+        assert!(reg.find("aaaaaa").is_some());
+        assert!(reg.find("aaaaaaa").is_none());
+    }
+
+    #[test]
+    fn test_backtracking_into_groups() {
+        let reg = Regex::new(r#"(a|ab)c"#).unwrap();
+        assert_eq!(reg.find("abc").map(|m| m.as_str()), Some("abc"));
+
+        let reg = Regex::new(r#"^(?:ab|a)*b$"#).unwrap();
+        assert!(reg.find("aab").is_some());
+
+        let reg = Regex::new(r#"(a+)(a+)"#).unwrap();
+        let caps = reg.captures("aaaa").unwrap();
+        assert_eq!(caps.get(1).unwrap().as_str(), "aaa");
+        assert_eq!(caps.get(2).unwrap().as_str(), "a");
+
+        let reg = Regex::new(r#"(a+?)(a+)"#).unwrap();
+        let caps = reg.captures("aaaa").unwrap();
+        assert_eq!(caps.get(1).unwrap().as_str(), "a");
+        assert_eq!(caps.get(2).unwrap().as_str(), "aaa");
+    }
+
+    #[test]
+    fn test_backtracking_restores_captures() {
+        let reg = Regex::new(r#"(?:(a)|b)*c"#).unwrap();
+        let caps = reg.captures("abc").unwrap();
+        assert_eq!(caps.get(1).unwrap().range(), 0..1);
+
+        let reg = Regex::new(r#"(a)?(?:ab)"#).unwrap();
+        let caps = reg.captures("ab").unwrap();
+        assert!(caps.get(1).is_none());
+    }
+
+    #[test]
+    fn test_empty_matches() {
+        let reg = Regex::new(r#"^$"#).unwrap();
+        assert!(reg.find("").is_some());
+        assert!(reg.find("a").is_none());
+
+        let reg = Regex::new(r#"a*"#).unwrap();
+        assert_eq!(reg.find("").unwrap().range(), 0..0);
+        assert_eq!(reg.find("baa").unwrap().range(), 0..0);
+
+        let reg = Regex::new(r#"$"#).unwrap();
+        assert_eq!(reg.find("abc").unwrap().range(), 3..3);
+
+        let reg = Regex::new(r#"(?:a*)*b"#).unwrap();
+        assert_eq!(reg.find("aab").map(|m| m.as_str()), Some("aab"));
+        assert!(reg.find("aac").is_none());
+    }
+
+    #[test]
+    fn test_char_class_ranges() {
+        let reg = Regex::new(r#"[a-z]+"#).unwrap();
+        assert_eq!(reg.find("ABC hello").map(|m| m.as_str()), Some("hello"));
+
+        let reg = Regex::new(r#"[^0-9]+"#).unwrap();
+        assert_eq!(reg.find("123abc456").map(|m| m.as_str()), Some("abc"));
+
+        let reg = Regex::new(r#"[a\-z]+"#).unwrap();
+        assert_eq!(reg.find("b-za").map(|m| m.as_str()), Some("-za"));
+
+        let reg = Regex::new(r#"[\]x-]+"#).unwrap();
+        assert_eq!(reg.find("a]-x").map(|m| m.as_str()), Some("]-x"));
+
+        let reg = Regex::new(r#"[^a]"#).unwrap();
+        assert_eq!(reg.find("aé").map(|m| m.as_str()), Some("é"));
+    }
+
+    #[test]
+    fn test_perl_classes() {
+        let reg = Regex::new(r#"\d+"#).unwrap();
+        assert_eq!(reg.find("abc 123").map(|m| m.as_str()), Some("123"));
+
+        let reg = Regex::new(r#"\w+\s\W\S\D"#).unwrap();
+        assert_eq!(
+            reg.find("!! foo_1 -xy").map(|m| m.as_str()),
+            Some("foo_1 -xy")
+        );
+
+        let reg = Regex::new(r#"[\d_]+"#).unwrap();
+        assert_eq!(reg.find("ab1_2c").map(|m| m.as_str()), Some("1_2"));
+
+        let reg = Regex::new(r#"[^\s]+"#).unwrap();
+        assert_eq!(reg.find("  foo bar").map(|m| m.as_str()), Some("foo"));
+
+        let reg = Regex::new(r#"a\tb\nc\0"#).unwrap();
+        assert!(reg.find("a\tb\nc\0").is_some());
+
+        let reg = Regex::new(r#"[\t-\n]+"#).unwrap();
+        assert_eq!(reg.find("a\t\n").map(|m| m.as_str()), Some("\t\n"));
+    }
+
+    #[test]
+    fn test_unicode_perl_classes() {
+        let reg = Regex::new(r#"\w+"#).unwrap();
+        assert_eq!(reg.find("¡señor!").map(|m| m.as_str()), Some("señor"));
+
+        let reg = Regex::new(r#"(?-u)\w+"#).unwrap();
+        assert_eq!(reg.find("¡señor!").map(|m| m.as_str()), Some("se"));
+
+        let reg = Regex::new(r#"\d"#).unwrap();
+        assert!(reg.find("٣").is_some());
+
+        let reg = Regex::new(r#"(?-u:\d)\d"#).unwrap();
+        assert!(reg.find("٣٣").is_none());
+        assert!(reg.find("3٣").is_some());
+
+        let reg = Regex::new(r#"(?-u)[\s\d]"#).unwrap();
+        assert!(reg.find("\u{3000}").is_none());
+        let reg = Regex::new(r#"\s"#).unwrap();
+        assert!(reg.find("\u{3000}").is_some());
+    }
+
+    #[test]
+    fn test_utf8_corpus() {
+        // 2-byte (é, ж), 3-byte (€, 中) and 4-byte (😀, 𝄞) code points
+        let corpus = [
+            ("llo", "héllo", Some((3, 6))),
+            ("é", "héllo", Some((1, 3))),
+            ("h.l", "héllo", Some((0, 4))),
+            ("^.$", "ж", Some((0, 2))),
+            ("^.$", "中", Some((0, 3))),
+            ("^.$", "😀", Some((0, 4))),
+            ("^..$", "😀", None),
+            ("€+", "5 €€€!", Some((2, 11))),
+            ("[€😀]+", "a😀€b", Some((1, 8))),
+            ("[^a]+", "a𝄞é", Some((1, 7))),
+            ("[α-ω]+", "abcλογος", Some((3, 13))),
+            ("中.*?文", "中国的中文", Some((0, 15))),
+            ("(?:😀|€)$", "x€😀", Some((4, 8))),
+            ("\\w+", "«жук»", Some((2, 8))),
+            ("a*", "😀a", Some((0, 0))),
+            ("$", "ж€😀", Some((9, 9))),
+            ("😀{2}", "😀 😀😀", Some((5, 13))),
+        ];
+
+        for (pattern, haystack, expected) in corpus {
+            let reg = Regex::new(pattern).unwrap();
+            let m = reg.find(haystack);
+            let bounds = m.map(|m| (m.start(), m.end()));
+            assert_eq!(bounds, expected, "{} on {}", pattern, haystack);
+
+            if let (Some(m), Some((start, end))) = (m, expected) {
+                assert!(haystack.is_char_boundary(start) && haystack.is_char_boundary(end));
+                assert_eq!(m.as_str(), &haystack[start..end]);
+                assert_eq!(m.len(), end - start);
+            }
+        }
+    }
+
+    #[test]
+    fn test_utf8_captures() {
+        let reg = Regex::new(r#"(?<word>\w+)\s(€|😀)+"#).unwrap();
+        let caps = reg.captures("→ naïve 😀€😀").unwrap();
+
+        assert_eq!(caps.get(0).unwrap().range(), 4..22);
+        assert_eq!(caps.name("word").unwrap().as_str(), "naïve");
+        assert_eq!(caps.get(2).unwrap().range(), 18..22);
+    }
+
+    #[test]
+    fn test_hex_escapes() {
+        let reg = Regex::new(r#"\x41[\x61-\x63]+"#).unwrap();
+        assert_eq!(reg.find("xAbca").map(|m| m.as_str()), Some("Abca"));
+
+        // In a `&str` regex `\xHH` is always the char U+00HH
+        let reg = Regex::new(r#"(?-u)caf\xE9"#).unwrap();
+        assert_eq!(reg.find("un café").unwrap().range(), 3..8);
+    }
+
+    #[test]
+    fn test_match_borrows_haystack() {
+        let haystack = String::from("id: 42");
+        let m = {
+            let reg = Regex::new(r#"\d+"#).unwrap();
+            reg.find(&haystack).unwrap()
+        };
+
+        assert_eq!((m.start(), m.end(), m.len()), (4, 6, 2));
+        assert_eq!(m.range(), 4..6);
+        assert_eq!(m.as_str(), "42");
+        assert_eq!(&haystack[m.range()], "42");
+        assert!(!m.is_empty());
+    }
+
+    #[test]
+    fn test_is_match() {
+        let reg = Regex::new(r#"\d{3}-\d{4}"#).unwrap();
+        assert!(reg.is_match("call 555-1234 now"));
+        assert!(!reg.is_match("call 555-123 now"));
+
+        let reg = Regex::new(r#"^$"#).unwrap();
+        assert!(reg.is_match(""));
+        assert!(!reg.is_match("\n"));
+    }
 
-        assert!(!reg.find("d").is_match());
-        assert!(!reg.find(" a").is_match());
+    #[test]
+    fn test_dfa_cache_fallback() {
+        let haystack = "ab".repeat(2000) + "abbbbbbbbb";
+
+        // Too small a cache for any search, which the PikeVM then runs
+        let reg = RegexBuilder::new(r#"(a|b)*a(a|b){8}"#)
+            .dfa_cache_capacity(0)
+            .build()
+            .unwrap();
+        assert_eq!(reg.engine_info(), EngineInfo::PikeVm);
+        assert!(reg.is_match(&haystack));
+        assert_eq!(reg.find(&haystack).unwrap().range(), 0..4009);
+
+        // Room for a few states only, so the lazy DFAs give up
+        let reg = RegexBuilder::new(r#"(a|b)*a(a|b){8}"#)
+            .dfa_cache_capacity(1 << 16)
+            .build()
+            .unwrap();
+        assert_eq!(reg.engine_info(), EngineInfo::LazyDfa);
+        assert!(reg.is_match(&haystack));
+        assert_eq!(reg.find(&haystack).unwrap().range(), 0..4009);
+
+        let reg = Regex::new(r#"(a|b)*a(a|b){8}"#).unwrap();
+        assert!(reg.is_match(&haystack));
+        assert_eq!(reg.find(&haystack).unwrap().range(), 0..4009);
+    }
+
+    #[test]
+    fn test_literal_prefilter() {
+        let log = "INFO: started\n".repeat(1000);
+
+        let reg = Regex::new(r#"ERROR: (\d+)"#).unwrap();
+        assert!(!reg.is_match(&log));
+        let haystack = log.clone() + "ERROR: none\nERROR: 42\n";
+        assert!(reg.is_match(&haystack));
+        let caps = reg.captures(&haystack).unwrap();
+        assert_eq!(caps.get(0).unwrap().range(), 14012..14021);
+        assert_eq!(caps.get(1).unwrap().as_str(), "42");
+
+        // Only the inner literal is known, and it rules out the log
+        let reg = Regex::new(r#"[a-z]+ failed: \w+"#).unwrap();
+        assert!(!reg.is_match(&log));
+        let haystack = log + "disk failed: sda";
+        assert_eq!(
+            reg.find(&haystack).map(|m| m.as_str()),
+            Some("disk failed: sda")
+        );
+    }
+
+    #[test]
+    fn test_keyword_alternation() {
+        let keywords: Vec<_> = (0..300).map(|i| format!("kw{}x", i)).collect();
+        let haystack = "nothing to see ".repeat(100) + "kw17x kw250x";
+
+        let reg = Regex::new(keywords.join("|")).unwrap();
+        assert_eq!(reg.engine_info(), EngineInfo::Literal);
+        assert_eq!(reg.find(&haystack).map(|m| m.as_str()), Some("kw17x"));
+        assert!(!reg.is_match("kw17 kw300x"));
+
+        let reg = Regex::new(format!("(?:{}) (\\w+)", keywords.join("|"))).unwrap();
+        assert_eq!(reg.engine_info(), EngineInfo::LazyDfa);
+        let caps = reg.captures(&haystack).unwrap();
+        assert_eq!(caps.get(0).unwrap().as_str(), "kw17x kw250x");
+        assert_eq!(caps.get(1).unwrap().as_str(), "kw250x");
+    }
+
+    #[test]
+    fn test_regex_set() {
+        let routes: Vec<_> = (0..300).map(|i| format!(r"^GET /r{}/\d+$", i)).collect();
+        let set = RegexSet::new(&routes).unwrap();
+
+        assert_eq!(set.len(), 300);
+        assert_eq!(set.matches("GET /r42/7").iter().collect::<Vec<_>>(), [42]);
+        assert!(!set.matches("GET /r42/x").matched_any());
+
+        let set = RegexSet::new([r"\bad", r"\w+"]);
+        assert!(matches!(set, Err(errors::SetError::BadPattern(0, _))));
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let ranges = |pattern: &str, haystack: &str| {
+            let reg = Regex::new(pattern).unwrap();
+            reg.find_iter(haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ranges(r#"\d+"#, "a1b22c333"), [(1, 2), (3, 5), (6, 9)]);
+        assert_eq!(ranges("ab", "abab"), [(0, 2), (2, 4)]);
+        assert_eq!(ranges("a|bc", "abcxa"), [(0, 1), (1, 3), (4, 5)]);
+        assert_eq!(ranges("x(\\d)", "x1 x2 x"), [(0, 2), (3, 5)]);
+        assert_eq!(ranges("a+?", "aaa"), [(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(ranges("^a", "aaa"), [(0, 1)]);
+        assert_eq!(ranges("a$", "aaa"), [(2, 3)]);
+        assert_eq!(ranges("x", "abc"), []);
+
+        // The PikeVM searches from each later start
+        let reg = RegexBuilder::new("a+?|é*")
+            .dfa_cache_capacity(0)
+            .build()
+            .unwrap();
+        assert_eq!(reg.engine_info(), EngineInfo::PikeVm);
+        let ranges: Vec<_> = reg
+            .find_iter("aaébé")
+            .map(|m| (m.start(), m.end()))
+            .collect();
+        assert_eq!(ranges, [(0, 1), (1, 2), (2, 4), (5, 7)]);
+    }
+
+    #[test]
+    fn test_find_iter_empty_matches() {
+        let ranges = |pattern: &str, haystack: &str| {
+            let reg = Regex::new(pattern).unwrap();
+            reg.find_iter(haystack)
+                .map(|m| (m.start(), m.end()))
+                .collect::<Vec<_>>()
+        };
+
+        // No empty match right after the previous match
+        assert_eq!(ranges("a*", "baaa"), [(0, 0), (1, 4)]);
+        assert_eq!(ranges("a*", "aba"), [(0, 1), (2, 3)]);
+        // Empty matches move on by whole chars
+        assert_eq!(ranges("", "é!"), [(0, 0), (2, 2), (3, 3)]);
+        assert_eq!(ranges("", ""), [(0, 0)]);
+        assert_eq!(ranges("é*", "aéé"), [(0, 0), (1, 5)]);
+
+        let reg = Regex::new("").unwrap();
+        let matched: Vec<_> = reg.find_iter("日本").map(|m| m.len()).collect();
+        assert_eq!(matched, [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_split() {
+        fn split<'h>(pattern: &str, haystack: &'h str) -> Vec<&'h str> {
+            let reg = Regex::new(pattern).unwrap();
+            reg.split(haystack).collect()
+        }
+
+        assert_eq!(split(r#"\s*[,;]\s*"#, "a, b ;c"), ["a", "b", "c"]);
+        assert_eq!(split(",", ",a,,b,"), ["", "a", "", "b", ""]);
+        assert_eq!(split(",", "abc"), ["abc"]);
+        assert_eq!(split(",", ""), [""]);
+        assert_eq!(split("", "aé"), ["", "a", "é", ""]);
+        assert_eq!(split("", ""), ["", ""]);
+        assert_eq!(split("x*", "axxb"), ["", "a", "b", ""]);
+        assert_eq!(split("^a", "aaa"), ["", "aa"]);
+
+        for (pattern, haystack) in [(",", ",a,,b,"), ("ab", "xabyabab"), ("", "abc")] {
+            let expected: Vec<_> = haystack.split(pattern).collect();
+            assert_eq!(split(pattern, haystack), expected);
+        }
+    }
+
+    #[test]
+    fn test_splitn() {
+        let reg = Regex::new(r#"\d"#).unwrap();
+        let splitn = |haystack, limit| reg.splitn(haystack, limit).collect::<Vec<_>>();
+
+        assert_eq!(splitn("a1b2c", 0), [""; 0]);
+        assert_eq!(splitn("a1b2c", 1), ["a1b2c"]);
+        assert_eq!(splitn("a1b2c", 2), ["a", "b2c"]);
+        assert_eq!(splitn("a1b2c", 3), ["a", "b", "c"]);
+        assert_eq!(splitn("a1b2c", 9), ["a", "b", "c"]);
+        assert_eq!(splitn("1", 2), ["", ""]);
+    }
+
+    #[test]
+    fn test_split_inclusive() {
+        fn split<'h>(pattern: &str, haystack: &'h str) -> Vec<&'h str> {
+            let reg = Regex::new(pattern).unwrap();
+            reg.split_inclusive(haystack).collect()
+        }
+
+        assert_eq!(split("\r?\n", "a\r\nb\nc"), ["a\r\n", "b\n", "c"]);
+        assert_eq!(split("\n", "a\nb\n"), ["a\n", "b\n"]);
+        assert_eq!(split("\n", ""), [""; 0]);
+
+        for (pattern, haystack) in [(",", ",a,,b,"), ("ab", "xabyabab"), ("", "ab")] {
+            let expected: Vec<_> = haystack.split_inclusive(pattern).collect();
+            assert_eq!(split(pattern, haystack), expected);
+        }
+    }
+
+    #[test]
+    fn test_replace() {
+        let reg = Regex::new(r#"(\w+)@(?<host>\w+)"#).unwrap();
+        let template = Template::new("${host}: $1 [$0] $$2 $3");
+
+        assert_eq!(
+            reg.replace("to a@b, c@d", &template),
+            "to b: a [a@b] $2 , c@d"
+        );
+        assert_eq!(
+            reg.replace_all("to a@b, c@d", &template),
+            "to b: a [a@b] $2 , d: c [c@d] $2 "
+        );
+        assert_eq!(reg.replacen("a@b c@d e@f", 2, "$2"), "b d e@f");
+        assert_eq!(reg.replacen("a@b c@d", 0, "$2"), "b d");
+
+        let reg = Regex::new("(a)|b").unwrap();
+        assert_eq!(reg.replace_all("abc", "[$1]"), "[a][]c");
+    }
+
+    #[test]
+    fn test_replacer() {
+        let reg = Regex::new(r#"(?<word>[a-z]+)(\d)?"#).unwrap();
+
+        assert_eq!(reg.replace_all("ab1 c", "<$2${word}>"), "<1ab> <c>");
+        assert_eq!(reg.replace_all("ab1 c", String::from("[$0]")), "[ab1] [c]");
+        assert_eq!(reg.replace_all("ab1 c", &String::from("$$")), "$ $");
+        assert_eq!(
+            reg.replace_all("ab1 c", NoExpand("${word}$1")),
+            "${word}$1 ${word}$1"
+        );
+
+        let upper = |caps: &Captures| caps.get(1).unwrap().as_str().to_uppercase();
+        assert_eq!(reg.replace_all("ab1 c", upper), "AB C");
+
+        let mut count = 0;
+        let numbered = reg.replace_all("x y z", |_: &Captures| {
+            count += 1;
+            count.to_string()
+        });
+        assert_eq!(numbered, "1 2 3");
+        assert_eq!(reg.replacen("x y z", 2, |_: &Captures| "_"), "_ _ z");
+    }
+
+    #[test]
+    fn test_replace_borrows_without_match() {
+        let reg = Regex::new(r#"\d+"#).unwrap();
+        let template = Template::new("#");
+
+        assert!(matches!(
+            reg.replace_all("abc", &template),
+            Cow::Borrowed("abc")
+        ));
+        assert!(matches!(reg.replacen("a1", 0, &template), Cow::Owned(_)));
+        assert_eq!(reg.replace_all("a1b22c", &template), "a#b#c");
+        assert_eq!(reg.replace_all("", &template), "");
+    }
+
+    #[test]
+    fn test_replace_empty_matches() {
+        let reg = Regex::new("a*").unwrap();
+        assert_eq!(reg.replace_all("baaac", "-"), "-b-c-");
+
+        let reg = Regex::new("").unwrap();
+        assert_eq!(reg.replace_all("é日", "|"), "|é|日|");
+        assert_eq!(reg.replace("é", "|"), "|é");
+    }
+
+    #[test]
+    fn test_engine_info() {
+        let reg = Regex::new("needle").unwrap();
+        assert_eq!(reg.engine_info(), EngineInfo::Literal);
+        assert!(reg.is_match("haystack with a needle"));
+        assert_eq!(reg.find("haystack with a needle").unwrap().range(), 16..22);
+        assert_eq!(
+            reg.captures("a needle").unwrap().get(0).unwrap().range(),
+            2..8
+        );
+        assert!(reg.find("haystack").is_none());
+
+        let reg = Regex::new(r#"^(\w+):(\d+)?"#).unwrap();
+        assert_eq!(reg.engine_info(), EngineInfo::Anchored);
+        assert!(reg.is_match("host:80 x"));
+        assert!(!reg.is_match(" host:80"));
+        assert_eq!(reg.find("host:80 x").unwrap().range(), 0..7);
+        let caps = reg.captures("host: x").unwrap();
+        assert_eq!(caps.get(1).unwrap().as_str(), "host");
+        assert!(caps.get(2).is_none());
+
+        // Groups are resolved over the match the lazy DFAs found
+        let reg = Regex::new(r#"(\w+)@(\w+)"#).unwrap();
+        assert_eq!(reg.engine_info(), EngineInfo::LazyDfa);
+        let caps = reg.captures("mail me@here or you@there").unwrap();
+        assert_eq!(caps.get(0).unwrap().range(), 5..12);
+        assert_eq!(caps.get(2).unwrap().as_str(), "here");
+    }
+
+    #[test]
+    fn test_builder_flags() {
+        let reg = RegexBuilder::new("ab[c-e]")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert_eq!(reg.find("xAbD").unwrap().range(), 1..4);
+        assert!(!reg.is_match("aBf"));
+        let reg = RegexBuilder::new("(?-i)a(?i)b")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(reg.is_match("aB") && !reg.is_match("AB"));
+        assert!(Regex::new("(?i)σ").unwrap().is_match("Σ"));
+        assert!(!Regex::new("(?i-u)é").unwrap().is_match("É"));
+
+        let reg = RegexBuilder::new(r#"^\w+$"#)
+            .multi_line(true)
+            .build()
+            .unwrap();
+        let lines: Vec<_> = reg
+            .find_iter("one\ntwo three\n\nfour")
+            .map(|m| m.as_str())
+            .collect();
+        assert_eq!(lines, ["one", "four"]);
+        let reg = RegexBuilder::new(r#"^$"#).multi_line(true).build().unwrap();
+        assert_eq!(reg.find_iter("a\n\nb\n").count(), 2);
+
+        let reg = RegexBuilder::new("a.c")
+            .dot_matches_new_line(false)
+            .build()
+            .unwrap();
+        assert!(!reg.is_match("a\nc"));
+        let reg = RegexBuilder::new("a.c")
+            .dot_matches_new_line(true)
+            .build()
+            .unwrap();
+        assert!(reg.is_match("a\nc"));
+
+        let reg = RegexBuilder::new("a b # comment\n [ ]c \\ d")
+            .ignore_whitespace(true)
+            .build()
+            .unwrap();
+        assert!(reg.is_match("ab c d"));
+
+        let reg = RegexBuilder::new(r#"\d"#).unicode(false).build().unwrap();
+        assert!(reg.is_match("7") && !reg.is_match("٣"));
+    }
+
+    #[test]
+    fn test_builder_limits() {
+        let err = RegexBuilder::new("(?:(?:a){100}){100}")
+            .size_limit(1 << 16)
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, errors::ParseError::SizeLimitExceeded(65536)));
+        assert!(RegexBuilder::new("(?:(?:a){100}){100}").build().is_ok());
+        assert!(Regex::new("(?:(?:a{1000}){1000}){1000}").is_err());
+
+        let err = RegexBuilder::new("((a))")
+            .nest_limit(1)
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, errors::ParseError::NestLimitExceeded(1, 1)));
+
+        let err = RegexBuilder::new("a{5,6}")
+            .repetition_limit(5)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            errors::ParseError::RepetitionLimitExceeded(1, 5)
+        ));
+
+        let reg = RegexBuilder::new("a{2000}")
+            .repetition_limit(2000)
+            .build()
+            .unwrap();
+        assert!(reg.is_match("a".repeat(2000)));
     }
 }
@@ -0,0 +1,822 @@
+//! Searches for literal strings, which also speed up regex searches by
+//! skipping to where a match can start.
+//!
+//! [`MultiLiteral`] finds the first of many literals at once:
+//!
+//! ```
+//! use regex_engine::literal::{MatchKind, MultiLiteral};
+//!
+//! let keywords = MultiLiteral::new(["disk", "disk full", "oom"]);
+//! let found = keywords.find("alert: disk full").unwrap();
+//! assert_eq!((found.pattern(), found.range()), (0, (7, 11)));
+//!
+//! let keywords = MultiLiteral::with_match_kind(["disk", "disk full"], MatchKind::LeftmostLongest);
+//! assert_eq!(keywords.find("alert: disk full").unwrap().range(), (7, 16));
+//! ```
+
+use crate::parser::AstNode;
+use crate::tokenizer::Char;
+
+/// Number of candidates the rare byte of a [`Finder`] gets to prove it
+/// skips more than a needle length each time on average.
+const RARE_BYTE_TRIES: usize = 32;
+
+/// Largest set of literals extracted as the prefixes of a pattern.
+const MAX_PREFIXES: usize = 1000;
+
+/// Largest class whose chars are extracted as literals.
+const MAX_CLASS_LITERALS: usize = 16;
+
+/// Literals found in every match of a pattern.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Required {
+    /// Every match starts with these bytes
+    pub prefix: Vec<u8>,
+    /// Every match ends with these bytes
+    pub suffix: Vec<u8>,
+    /// The longest literal known to be somewhere in every match
+    pub inner: Vec<u8>,
+    /// Whether every match is exactly `prefix`, which also means the pattern
+    /// has no assertions
+    pub exact: bool,
+}
+
+impl Required {
+    /// Extracts the literals required by `node`.
+    pub fn new(node: &AstNode) -> Self {
+        match node {
+            AstNode::Char(Char::Lit(c)) => Self::exact(c.to_string().into_bytes()),
+            AstNode::Chain(nodes) => nodes
+                .iter()
+                .map(Self::new)
+                .fold(Self::exact(vec![]), Self::concat),
+            AstNode::Alternation(branches) => branches
+                .iter()
+                .map(Self::new)
+                .reduce(Self::union)
+                .unwrap_or_default(),
+            AstNode::Quantifier(q, _, node) => match q.bounds() {
+                (0, _) => Self::default(),
+                (1, Some(1)) => Self::new(node),
+                _ => Self {
+                    exact: false,
+                    ..Self::new(node)
+                },
+            },
+            AstNode::NonCapturingGroup(node) | AstNode::CaptureGroup(_, _, node) => Self::new(node),
+            AstNode::Char(_)
+            | AstNode::CharClass(_)
+            | AstNode::ByteClass(_)
+            | AstNode::StartAnchor
+            | AstNode::EndAnchor
+            | AstNode::StartLineAnchor
+            | AstNode::EndLineAnchor => Self::default(),
+        }
+    }
+
+    fn exact(bytes: Vec<u8>) -> Self {
+        Self {
+            prefix: bytes.clone(),
+            suffix: bytes.clone(),
+            inner: bytes,
+            exact: true,
+        }
+    }
+
+    /// Literals of `a` followed by `b`.
+    fn concat(a: Self, b: Self) -> Self {
+        if a.exact && b.exact {
+            return Self::exact([a.prefix, b.prefix].concat());
+        }
+
+        let prefix = match a.exact {
+            true => [&a.prefix[..], &b.prefix].concat(),
+            false => a.prefix,
+        };
+        let suffix = match b.exact {
+            true => [&a.suffix[..], &b.suffix].concat(),
+            false => b.suffix,
+        };
+        let junction = [a.suffix, b.prefix].concat();
+        let inner = longest([a.inner, b.inner, junction, prefix.clone(), suffix.clone()]);
+
+        Self {
+            prefix,
+            suffix,
+            inner,
+            exact: false,
+        }
+    }
+
+    /// Literals of either `a` or `b`.
+    fn union(a: Self, b: Self) -> Self {
+        if a.exact && b.exact && a.prefix == b.prefix {
+            return a;
+        }
+
+        let common_prefix = a
+            .prefix
+            .iter()
+            .zip(&b.prefix)
+            .take_while(|(x, y)| x == y)
+            .count();
+        let common_suffix = a
+            .suffix
+            .iter()
+            .rev()
+            .zip(b.suffix.iter().rev())
+            .take_while(|(x, y)| x == y)
+            .count();
+        let prefix = a.prefix[..common_prefix].to_vec();
+        let suffix = a.suffix[a.suffix.len() - common_suffix..].to_vec();
+
+        Self {
+            inner: longest([prefix.clone(), suffix.clone()]),
+            prefix,
+            suffix,
+            exact: false,
+        }
+    }
+}
+
+/// A finite set of literals, one of which starts every match of a pattern,
+/// in the order the pattern prefers them.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Prefixes {
+    pub literals: Vec<Vec<u8>>,
+    /// Whether every match is exactly one of the literals, which also means
+    /// the pattern has no assertions
+    pub exact: bool,
+}
+
+impl Prefixes {
+    /// Extracts the prefixes of `node`.
+    pub fn new(node: &AstNode) -> Self {
+        match node {
+            AstNode::Char(Char::Lit(c)) => Self::exact(vec![c.to_string().into_bytes()]),
+            AstNode::CharClass(class) => {
+                let chars = class
+                    .matched_ranges()
+                    .into_iter()
+                    .flat_map(|(start, end)| start..=end)
+                    .take(MAX_CLASS_LITERALS + 1)
+                    .map(|c| c.to_string().into_bytes())
+                    .collect::<Vec<_>>();
+                match chars.len() <= MAX_CLASS_LITERALS {
+                    true => Self::exact(chars),
+                    false => Self::unknown(),
+                }
+            }
+            AstNode::Chain(nodes) => nodes
+                .iter()
+                .map(Self::new)
+                .fold(Self::exact(vec![vec![]]), Self::concat),
+            AstNode::Alternation(branches) => branches
+                .iter()
+                .map(Self::new)
+                .reduce(Self::union)
+                .unwrap_or_else(Self::unknown),
+            AstNode::Quantifier(q, _, node) => match q.bounds() {
+                (0, _) => Self::unknown(),
+                (1, Some(1)) => Self::new(node),
+                _ => Self {
+                    exact: false,
+                    ..Self::new(node)
+                },
+            },
+            AstNode::NonCapturingGroup(node) | AstNode::CaptureGroup(_, _, node) => Self::new(node),
+            AstNode::Char(_)
+            | AstNode::ByteClass(_)
+            | AstNode::StartAnchor
+            | AstNode::EndAnchor
+            | AstNode::StartLineAnchor
+            | AstNode::EndLineAnchor => Self::unknown(),
+        }
+    }
+
+    fn exact(literals: Vec<Vec<u8>>) -> Self {
+        Self {
+            literals,
+            exact: true,
+        }
+    }
+
+    /// Matches may start with anything.
+    fn unknown() -> Self {
+        Self {
+            literals: vec![vec![]],
+            exact: false,
+        }
+    }
+
+    /// Whether the literals can tell where a match may start.
+    pub fn is_useful(&self) -> bool {
+        self.literals.iter().all(|literal| !literal.is_empty())
+    }
+
+    /// Prefixes of `a` followed by `b`.
+    fn concat(a: Self, b: Self) -> Self {
+        if !a.exact {
+            return a;
+        }
+        if a.literals.len() * b.literals.len() > MAX_PREFIXES {
+            return Self { exact: false, ..a };
+        }
+
+        let literals = a
+            .literals
+            .iter()
+            .flat_map(|x| b.literals.iter().map(move |y| [&x[..], y].concat()))
+            .collect();
+        Self {
+            literals,
+            exact: b.exact,
+        }
+    }
+
+    /// Prefixes of either `a` or `b`.
+    fn union(mut a: Self, b: Self) -> Self {
+        if a.literals.len() + b.literals.len() > MAX_PREFIXES {
+            return Self::unknown();
+        }
+
+        a.literals.extend(b.literals);
+        Self {
+            literals: a.literals,
+            exact: a.exact && b.exact,
+        }
+    }
+}
+
+/// Returns the first of the longest literals.
+fn longest<const N: usize>(literals: [Vec<u8>; N]) -> Vec<u8> {
+    literals
+        .into_iter()
+        .reduce(|longest, literal| match literal.len() > longest.len() {
+            true => literal,
+            false => longest,
+        })
+        .unwrap_or_default()
+}
+
+/// Cheap check run before a search, using literals found in every match.
+#[derive(Debug)]
+pub(crate) enum Prefilter {
+    /// Matches start with the literal, so none starts before it
+    Prefix(Finder),
+    /// Matches start with one of the literals
+    Prefixes(MultiLiteral),
+    /// Matches contain the literal, so there are none without it
+    Inner(Finder),
+}
+
+impl Prefilter {
+    /// Builds a prefilter from several `prefixes`, or else the prefix of
+    /// `required` or its longest other literal, if there is any.
+    pub fn new(required: &Required, prefixes: &Prefixes) -> Option<Self> {
+        if prefixes.literals.len() > 1 && prefixes.is_useful() {
+            return Some(Prefilter::Prefixes(MultiLiteral::new(&prefixes.literals)));
+        }
+        if !required.prefix.is_empty() {
+            return Some(Prefilter::Prefix(Finder::new(&required.prefix)));
+        }
+
+        let longest = match required.inner.len() < required.suffix.len() {
+            true => &required.suffix,
+            false => &required.inner,
+        };
+        (!longest.is_empty()).then(|| Prefilter::Inner(Finder::new(longest)))
+    }
+
+    /// Returns where a match may start at the earliest, or `None` if there
+    /// cannot be any in `haystack`.
+    pub fn skip(&self, haystack: &[u8]) -> Option<usize> {
+        match self {
+            Prefilter::Prefix(finder) => finder.find(haystack),
+            Prefilter::Prefixes(literals) => literals.find(haystack).map(|found| found.start),
+            Prefilter::Inner(finder) => finder.find(haystack).map(|_| 0),
+        }
+    }
+}
+
+/// Substring search. Candidates are found by scanning for the needle byte
+/// least common in typical text, and if that byte turns out to be common in
+/// the haystack, the search switches to Boyer–Moore–Horspool.
+#[derive(Debug)]
+pub(crate) struct Finder {
+    needle: Box<[u8]>,
+    /// Index of the rare byte in the needle
+    rare: usize,
+    /// How far the needle can move when a byte is under its last byte
+    shifts: Box<[usize; 256]>,
+}
+
+impl Finder {
+    pub fn new(needle: &[u8]) -> Self {
+        let rare = (0..needle.len())
+            .min_by_key(|&i| frequency(needle[i]))
+            .unwrap_or(0);
+
+        let mut shifts = Box::new([needle.len(); 256]);
+        for (i, &byte) in needle
+            .iter()
+            .enumerate()
+            .take(needle.len().saturating_sub(1))
+        {
+            shifts[byte as usize] = needle.len() - 1 - i;
+        }
+
+        Self {
+            needle: needle.into(),
+            rare,
+            shifts,
+        }
+    }
+
+    pub fn needle(&self) -> &[u8] {
+        &self.needle
+    }
+
+    /// Returns the start of the first occurrence of the needle.
+    pub fn find(&self, haystack: &[u8]) -> Option<usize> {
+        let len = self.needle.len();
+        if len == 0 {
+            return Some(0);
+        }
+
+        let (mut pos, mut tries_from, mut tries) = (0, 0, 0);
+        while pos + len <= haystack.len() {
+            if tries == RARE_BYTE_TRIES {
+                if pos - tries_from < tries * len {
+                    return self.horspool(haystack, pos);
+                }
+                (tries_from, tries) = (pos, 0);
+            }
+
+            // The rare byte must leave room for the rest of the needle
+            let window = &haystack[pos + self.rare..haystack.len() - len + self.rare + 1];
+            let start = pos + memchr(self.needle[self.rare], window)?;
+            if haystack[start..start + len] == *self.needle {
+                return Some(start);
+            }
+            pos = start + 1;
+            tries += 1;
+        }
+        None
+    }
+
+    fn horspool(&self, haystack: &[u8], mut pos: usize) -> Option<usize> {
+        let len = self.needle.len();
+        while pos + len <= haystack.len() {
+            let last = haystack[pos + len - 1];
+            if last == self.needle[len - 1]
+                && haystack[pos..pos + len - 1] == self.needle[..len - 1]
+            {
+                return Some(pos);
+            }
+            pos += self.shifts[last as usize];
+        }
+        None
+    }
+}
+
+/// How [`MultiLiteral`] picks between matches starting at the same
+/// position.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// The literal given first wins, like the branches of an alternation
+    #[default]
+    LeftmostFirst,
+    /// The longest literal wins
+    LeftmostLongest,
+}
+
+/// A literal found by [`MultiLiteral::find`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    pattern: usize,
+    start: usize,
+    end: usize,
+}
+
+impl Match {
+    /// Index of the literal that matched.
+    pub fn pattern(&self) -> usize {
+        self.pattern
+    }
+
+    /// Byte offsets of the match in the haystack.
+    pub fn range(&self) -> (usize, usize) {
+        (self.start, self.end)
+    }
+}
+
+type StateId = usize;
+
+const ROOT: StateId = 0;
+
+/// State of the trie of literals.
+#[derive(Debug, Default)]
+struct Node {
+    /// Children sorted by byte
+    next: Vec<(u8, StateId)>,
+    /// State of the longest proper suffix of this one that is in the trie
+    fail: StateId,
+    /// Length of the literal prefix this state stands for
+    depth: usize,
+    /// Literal ending here, the first one given if several are equal
+    output: Option<usize>,
+    /// Nearest state on the failure chain with an output
+    dict: Option<StateId>,
+}
+
+impl Node {
+    fn child(&self, byte: u8) -> Option<StateId> {
+        self.next
+            .binary_search_by_key(&byte, |&(b, _)| b)
+            .ok()
+            .map(|i| self.next[i].1)
+    }
+}
+
+/// Searches for many literals at once with an Aho–Corasick automaton, in
+/// time linear in the haystack however many literals there are.
+#[derive(Debug)]
+pub struct MultiLiteral {
+    nodes: Vec<Node>,
+    /// Transitions of the root, where the search spends most of its time
+    root: Box<[StateId; 256]>,
+    /// Bytes starting some literal, which the search skips to
+    starts: Box<[bool; 256]>,
+    kind: MatchKind,
+}
+
+impl MultiLiteral {
+    /// Builds a searcher for `literals`, reporting leftmost-first matches.
+    pub fn new<I, L>(literals: I) -> Self
+    where
+        I: IntoIterator<Item = L>,
+        L: AsRef<[u8]>,
+    {
+        Self::with_match_kind(literals, MatchKind::LeftmostFirst)
+    }
+
+    /// Builds a searcher for `literals`, picking between matches starting
+    /// at the same position according to `kind`.
+    pub fn with_match_kind<I, L>(literals: I, kind: MatchKind) -> Self
+    where
+        I: IntoIterator<Item = L>,
+        L: AsRef<[u8]>,
+    {
+        let mut nodes = vec![Node::default()];
+        for (pattern, literal) in literals.into_iter().enumerate() {
+            let mut id = ROOT;
+            for &byte in literal.as_ref() {
+                id = match nodes[id].child(byte) {
+                    Some(child) => child,
+                    None => {
+                        let child = nodes.len();
+                        nodes.push(Node {
+                            depth: nodes[id].depth + 1,
+                            ..Node::default()
+                        });
+                        let next = &mut nodes[id].next;
+                        let i = next.partition_point(|&(b, _)| b < byte);
+                        next.insert(i, (byte, child));
+                        child
+                    }
+                };
+            }
+            nodes[id].output.get_or_insert(pattern);
+        }
+
+        let mut root = Box::new([ROOT; 256]);
+        let mut starts = Box::new([false; 256]);
+        for &(byte, child) in &nodes[ROOT].next {
+            root[byte as usize] = child;
+            starts[byte as usize] = true;
+        }
+
+        // Parents come before children in breadth-first order, so their
+        // failure links are known
+        let mut queue = std::collections::VecDeque::from([ROOT]);
+        while let Some(id) = queue.pop_front() {
+            for i in 0..nodes[id].next.len() {
+                let (byte, child) = nodes[id].next[i];
+                queue.push_back(child);
+
+                let mut fail = nodes[id].fail;
+                let fail = match id {
+                    ROOT => ROOT,
+                    _ => loop {
+                        if let Some(next) = nodes[fail].child(byte) {
+                            break next;
+                        }
+                        if fail == ROOT {
+                            break ROOT;
+                        }
+                        fail = nodes[fail].fail;
+                    },
+                };
+                nodes[child].fail = fail;
+                nodes[child].dict = match nodes[fail].output {
+                    Some(_) => Some(fail),
+                    None => nodes[fail].dict,
+                };
+            }
+        }
+
+        Self {
+            nodes,
+            root,
+            starts,
+            kind,
+        }
+    }
+
+    /// Returns the leftmost match in `haystack`, picking between the
+    /// literals starting there according to the match kind.
+    pub fn find(&self, haystack: impl AsRef<[u8]>) -> Option<Match> {
+        let haystack = haystack.as_ref();
+        let mut best: Option<Match> = None;
+        let mut id = ROOT;
+        let mut pos = 0;
+
+        loop {
+            // Report the literals ending here, longest first
+            let mut output = match self.nodes[id].output {
+                Some(_) => Some(id),
+                None => self.nodes[id].dict,
+            };
+            while let Some(out) = output {
+                let found = Match {
+                    pattern: self.nodes[out].output.unwrap(),
+                    start: pos - self.nodes[out].depth,
+                    end: pos,
+                };
+                if best.is_none_or(|best| self.is_better(found, best)) {
+                    best = Some(found);
+                }
+                output = self.nodes[out].dict;
+            }
+
+            // Literals ending later start at least as far back as the
+            // current state goes
+            if let Some(best) = best {
+                if pos - self.nodes[id].depth > best.start {
+                    return Some(best);
+                }
+            }
+            if id == ROOT && best.is_none() {
+                pos += haystack[pos..]
+                    .iter()
+                    .position(|&byte| self.starts[byte as usize])?;
+            }
+            let Some(&byte) = haystack.get(pos) else {
+                return best;
+            };
+
+            id = self.next(id, byte);
+            pos += 1;
+        }
+    }
+
+    fn next(&self, mut id: StateId, byte: u8) -> StateId {
+        loop {
+            if id == ROOT {
+                return self.root[byte as usize];
+            }
+            if let Some(next) = self.nodes[id].child(byte) {
+                return next;
+            }
+            id = self.nodes[id].fail;
+        }
+    }
+
+    fn is_better(&self, found: Match, best: Match) -> bool {
+        if found.start != best.start {
+            return found.start < best.start;
+        }
+        match self.kind {
+            MatchKind::LeftmostFirst => found.pattern < best.pattern,
+            MatchKind::LeftmostLongest => found.end > best.end,
+        }
+    }
+}
+
+/// Returns the index of the first `byte` in `haystack`, checking a word at
+/// a time.
+fn memchr(byte: u8, haystack: &[u8]) -> Option<usize> {
+    const WORD: usize = std::mem::size_of::<usize>();
+    const LO: usize = usize::MAX / 255;
+    const HI: usize = LO << 7;
+
+    let repeated = LO * byte as usize;
+    let mut chunks = haystack.chunks_exact(WORD);
+    for (i, chunk) in chunks.by_ref().enumerate() {
+        // Bytes equal to `byte` are zero after the xor
+        let word = usize::from_ne_bytes(chunk.try_into().unwrap()) ^ repeated;
+        if word.wrapping_sub(LO) & !word & HI != 0 {
+            return chunk.iter().position(|&b| b == byte).map(|j| i * WORD + j);
+        }
+    }
+
+    let rest = chunks.remainder();
+    let offset = haystack.len() - rest.len();
+    rest.iter().position(|&b| b == byte).map(|j| offset + j)
+}
+
+/// Rough frequency of a byte in text, higher being more common.
+fn frequency(byte: u8) -> u8 {
+    const LETTERS: &[u8] = b"etaoinshrdlcumwfgypbvkjxqz";
+
+    match byte {
+        b' ' => 255,
+        b'a'..=b'z' => 250 - 4 * LETTERS.iter().position(|&l| l == byte).unwrap() as u8,
+        b'\n' | b',' | b'.' | b'-' | b'_' | b'/' | b':' | b'=' | b'"' | b'\'' => 150,
+        b'0'..=b'9' => 120,
+        b'A'..=b'Z' => 100,
+        b'\t' | b'\r' | b'(' | b')' | b';' => 90,
+        0x21..=0x7E => 50,
+        // UTF-8 leading and continuation bytes
+        0x80..=0xF4 => 40,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::tokenizer::tokenize;
+
+    fn required(pattern: &str) -> Required {
+        Required::new(&parse(tokenize(pattern.into()).unwrap()).unwrap())
+    }
+
+    fn prefixes(pattern: &str) -> Prefixes {
+        Prefixes::new(&parse(tokenize(pattern.into()).unwrap()).unwrap())
+    }
+
+    fn build_prefilter(pattern: &str) -> Option<Prefilter> {
+        Prefilter::new(&required(pattern), &prefixes(pattern))
+    }
+
+    fn literals(prefix: &str, suffix: &str, inner: &str) -> Required {
+        Required {
+            prefix: prefix.into(),
+            suffix: suffix.into(),
+            inner: inner.into(),
+            exact: false,
+        }
+    }
+
+    #[test]
+    fn test_required() {
+        assert_eq!(required("(?:ab)c"), Required::exact(b"abc".to_vec()));
+        assert_eq!(required("ERROR: \\d+"), literals("ERROR: ", "", "ERROR: "));
+        assert_eq!(
+            required("\\w+@example\\.com"),
+            literals("", "@example.com", "@example.com")
+        );
+        assert_eq!(required("\\d+foo(bar)?\\d"), literals("", "", "foo"));
+        assert_eq!(required("ab+c"), literals("ab", "bc", "ab"));
+        assert_eq!(required("x(?:abc|abd)"), literals("xab", "", "xab"));
+        assert_eq!(required("a|b"), literals("", "", ""));
+        assert_eq!(required("^ab$"), literals("", "", "ab"));
+        assert_eq!(required("(?:ab)*c"), literals("", "c", "c"));
+        assert_eq!(required("é"), Required::exact("é".into()));
+    }
+
+    #[test]
+    fn test_prefixes() {
+        let literals = |literals: &[&str], exact| Prefixes {
+            literals: literals.iter().map(|l| l.as_bytes().to_vec()).collect(),
+            exact,
+        };
+
+        assert_eq!(prefixes("foo|bar"), literals(&["foo", "bar"], true));
+        assert_eq!(
+            prefixes("(?:a|b)(?:c|d)"),
+            literals(&["ac", "ad", "bc", "bd"], true)
+        );
+        assert_eq!(prefixes("[ab]x+"), literals(&["ax", "bx"], false));
+        assert_eq!(prefixes("(foo|bar)\\d"), literals(&["foo", "bar"], false));
+        assert_eq!(prefixes("foo|\\w"), literals(&["foo", ""], false));
+        assert_eq!(prefixes("^foo"), literals(&[""], false));
+        assert!(!prefixes("foo|\\w").is_useful());
+
+        // Too many combinations to keep track of
+        let set = prefixes("[0-9a-f][0-9a-f][0-9a-f]x");
+        assert_eq!(set.literals.len(), 256);
+        assert!(!set.exact);
+    }
+
+    #[test]
+    fn test_prefilter() {
+        let prefilter = build_prefilter("ERROR: \\d+").unwrap();
+        assert_eq!(prefilter.skip(b"ok\nERROR: 12"), Some(3));
+        assert_eq!(prefilter.skip(b"ok\nWARN: 12"), None);
+
+        let prefilter = build_prefilter("\\w+@example\\.com").unwrap();
+        assert_eq!(prefilter.skip(b"me@example.com"), Some(0));
+        assert_eq!(prefilter.skip(b"me@example.org"), None);
+
+        let prefilter = build_prefilter("(?:warn|error): \\w+").unwrap();
+        assert!(matches!(prefilter, Prefilter::Prefixes(_)));
+        assert_eq!(prefilter.skip(b"info: a\nerror: b"), Some(8));
+
+        assert!(build_prefilter("\\w+|b").is_none());
+    }
+
+    #[test]
+    fn test_memchr() {
+        let haystack: Vec<u8> = (0..100).collect();
+        for byte in 0..100 {
+            assert_eq!(memchr(byte, &haystack), Some(byte as usize));
+            assert_eq!(memchr(byte, &haystack[byte as usize + 1..]), None);
+        }
+        assert_eq!(memchr(0xFF, &[0x7F; 20]), None);
+    }
+
+    #[test]
+    fn test_finder() {
+        let naive = |haystack: &[u8], needle: &[u8]| {
+            haystack
+                .windows(needle.len())
+                .position(|window| window == needle)
+        };
+        let haystacks = [
+            "ab".repeat(100) + "abb",
+            "a".repeat(300) + "z" + &"a".repeat(300),
+            "the quick brown fox jumps over the lazy dog".into(),
+            "".into(),
+        ];
+        let needles = [
+            "abb", "ba", "aaz", "aza", "zz", "fox", "the lazy", "x", "dog",
+        ];
+
+        for haystack in &haystacks {
+            for needle in needles {
+                let finder = Finder::new(needle.as_bytes());
+                assert_eq!(
+                    finder.find(haystack.as_bytes()),
+                    naive(haystack.as_bytes(), needle.as_bytes()),
+                    "{:?} in {:?}",
+                    needle,
+                    haystack
+                );
+            }
+        }
+        assert_eq!(Finder::new(b"").find(b"abc"), Some(0));
+    }
+
+    #[test]
+    fn test_multi_literal() {
+        let literals = MultiLiteral::new(["abcd", "bc", "b", "abce"]);
+        let found = literals.find("xabcex").unwrap();
+        assert_eq!((found.pattern(), found.range()), (3, (1, 5)));
+        let found = literals.find("xabx").unwrap();
+        assert_eq!((found.pattern(), found.range()), (2, (2, 3)));
+        assert!(literals.find("xacx").is_none());
+
+        let literals = MultiLiteral::new(["samwise", "sam"]);
+        assert_eq!(literals.find("samwise").unwrap().range(), (0, 7));
+        let literals = MultiLiteral::new(["sam", "samwise"]);
+        assert_eq!(literals.find("samwise").unwrap().range(), (0, 3));
+        let literals =
+            MultiLiteral::with_match_kind(["sam", "samwise"], MatchKind::LeftmostLongest);
+        assert_eq!(literals.find("samwise").unwrap().range(), (0, 7));
+
+        let literals = MultiLiteral::new(["", "a"]);
+        assert_eq!(literals.find("a").unwrap().range(), (0, 0));
+        assert!(MultiLiteral::new([""; 0]).find("a").is_none());
+    }
+
+    #[test]
+    fn test_multi_literal_matches_naive() {
+        let literals = ["he", "she", "his", "hers", "s", "rs", "ersh"];
+        let haystacks = ["ushers", "ahishers", "xxersh", "hhhhe", "", "zzz"];
+
+        for kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest] {
+            let searcher = MultiLiteral::with_match_kind(literals, kind);
+            for haystack in haystacks {
+                let expected = (0..haystack.len()).find_map(|start| {
+                    let found = literals
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, l)| haystack[start..].starts_with(*l));
+                    let found = match kind {
+                        MatchKind::LeftmostFirst => found.min_by_key(|&(i, _)| i),
+                        MatchKind::LeftmostLongest => {
+                            found.min_by_key(|&(i, l)| (usize::MAX - l.len(), i))
+                        }
+                    };
+                    found.map(|(i, l)| (i, (start, start + l.len())))
+                });
+                let found = searcher.find(haystack).map(|m| (m.pattern(), m.range()));
+                assert_eq!(found, expected, "{:?} in {:?}", kind, haystack);
+            }
+        }
+    }
+}
@@ -0,0 +1,586 @@
+use crate::class::CharClass;
+use crate::errors::{ParseError, SetError};
+use crate::parser::AstNode;
+use crate::tokenizer::Char;
+use crate::utf8;
+
+pub type StateId = usize;
+
+/// Maximum heap size of a compiled program accepted by default, in bytes.
+pub const DEFAULT_SIZE_LIMIT: usize = 10 << 20;
+
+/// Zero-width assertions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Look {
+    StartText,
+    EndText,
+    /// At the start of the text or right after a `\n`
+    StartLine,
+    /// At the end of the text or right before a `\n`
+    EndLine,
+}
+
+/// An instruction of a Thompson NFA working on bytes. Chars are compiled
+/// to the byte sequences of their UTF-8 encoding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    /// Consumes the given byte
+    Char {
+        byte: u8,
+        next: StateId,
+    },
+    /// Consumes a byte in one of the sorted, disjoint inclusive ranges and
+    /// goes to the state of that range
+    Class {
+        ranges: Box<[(u8, u8, StateId)]>,
+    },
+    /// Follows both states, preferring the first one
+    Split {
+        first: StateId,
+        second: StateId,
+    },
+    Jump(StateId),
+    /// Records the current position in a capture slot
+    Save {
+        slot: usize,
+        next: StateId,
+    },
+    Assert {
+        look: Look,
+        next: StateId,
+    },
+    Match,
+}
+
+/// A compiled pattern. Matching starts at `start` and succeeds on reaching
+/// the `Match` instruction, with slots 0 and 1 holding the match bounds.
+#[derive(Debug)]
+pub struct Program {
+    pub insts: Vec<Inst>,
+    pub start: StateId,
+    /// Same as `start`, preceded by a lazy loop over any byte so that a
+    /// match may start anywhere
+    pub start_unanchored: StateId,
+    /// Number of capture slots, two per group including group 0
+    pub slots_len: usize,
+    /// The `Match` state of each pattern, in increasing order
+    pub matches: Vec<StateId>,
+}
+
+impl Program {
+    /// Compiles `ast`, failing once the program would take more than
+    /// `size_limit` bytes.
+    pub fn compile(ast: &AstNode, size_limit: usize) -> Result<Self, ParseError> {
+        let mut compiler = Compiler::new(false, size_limit);
+
+        let end = compiler.push(Inst::Match);
+        let end = compiler.push(Inst::Save { slot: 1, next: end });
+        let body = compiler.compile(ast, end);
+        let start = compiler.push(Inst::Save {
+            slot: 0,
+            next: body,
+        });
+        compiler.check_size()?;
+
+        Ok(Self {
+            start_unanchored: compiler.unanchored(start),
+            insts: compiler.insts,
+            start,
+            slots_len: 2 * (ast.captures_len() + 1),
+            matches: vec![0],
+        })
+    }
+
+    /// Compiles a program matching the reverse of the byte strings matched
+    /// by `ast`, without capture slots: it starts at the end of a match and
+    /// reaches `Match` at its start.
+    pub fn compile_reverse(ast: &AstNode, size_limit: usize) -> Result<Self, ParseError> {
+        let mut compiler = Compiler::new(true, size_limit);
+
+        let end = compiler.push(Inst::Match);
+        let start = compiler.compile(ast, end);
+        compiler.check_size()?;
+
+        Ok(Self {
+            start_unanchored: compiler.unanchored(start),
+            insts: compiler.insts,
+            start,
+            slots_len: 0,
+            matches: vec![0],
+        })
+    }
+
+    /// Compiles a program matching any of `asts`, without capture slots,
+    /// where each of them reaches its own `Match` state. Fails with the
+    /// index of the pattern that takes the program over `size_limit` bytes.
+    pub fn compile_set(asts: &[AstNode], size_limit: usize) -> Result<Self, SetError> {
+        let mut compiler = Compiler::new(false, size_limit);
+
+        let mut matches = vec![];
+        let mut starts = vec![];
+        for (index, ast) in asts.iter().enumerate() {
+            let end = compiler.push(Inst::Match);
+            matches.push(end);
+            starts.push(compiler.compile(ast, end));
+            compiler
+                .check_size()
+                .map_err(|err| SetError::BadPattern(index, err))?;
+        }
+
+        let start = match starts
+            .into_iter()
+            .rev()
+            .reduce(|second, first| compiler.push(Inst::Split { first, second }))
+        {
+            Some(start) => start,
+            // An empty set never matches
+            None => compiler.push(Inst::Class { ranges: [].into() }),
+        };
+
+        Ok(Self {
+            start_unanchored: compiler.unanchored(start),
+            insts: compiler.insts,
+            start,
+            slots_len: 0,
+            matches,
+        })
+    }
+
+    /// Returns the index of the pattern whose `Match` state is `id`.
+    pub fn pattern_of(&self, id: StateId) -> Option<usize> {
+        self.matches.binary_search(&id).ok()
+    }
+}
+
+/// Compiles nodes back to front: each node is compiled knowing the state
+/// that follows it, so only loops need patching.
+struct Compiler {
+    insts: Vec<Inst>,
+    /// Whether to compile the reverse of each node
+    reverse: bool,
+    /// Heap size of `insts` so far, in bytes
+    size: usize,
+    size_limit: usize,
+}
+
+impl Compiler {
+    fn new(reverse: bool, size_limit: usize) -> Self {
+        Self {
+            insts: vec![],
+            reverse,
+            size: 0,
+            size_limit,
+        }
+    }
+
+    fn push(&mut self, inst: Inst) -> StateId {
+        self.size += std::mem::size_of::<Inst>();
+        if let Inst::Class { ranges } = &inst {
+            self.size += std::mem::size_of_val(&**ranges);
+        }
+        self.insts.push(inst);
+        self.insts.len() - 1
+    }
+
+    fn check_size(&self) -> Result<(), ParseError> {
+        match self.size > self.size_limit {
+            true => Err(ParseError::SizeLimitExceeded(self.size_limit)),
+            false => Ok(()),
+        }
+    }
+
+    /// Prefixes `start` with a lazy loop over any byte.
+    fn unanchored(&mut self, start: StateId) -> StateId {
+        let split = self.push(Inst::Jump(0));
+        let any = self.push(Inst::Class {
+            ranges: [(0x00, 0xFF, split)].into(),
+        });
+        self.insts[split] = self.split(any, start, false);
+        split
+    }
+
+    /// Compiles `node` so that matching it leads to `next`, returning the
+    /// state where it starts.
+    fn compile(&mut self, node: &AstNode, next: StateId) -> StateId {
+        // Stop expanding nested repetitions once the result is too large
+        // anyway, leaving `check_size` to report it
+        if self.size > self.size_limit {
+            return next;
+        }
+
+        match node {
+            AstNode::Chain(nodes) if self.reverse => nodes
+                .iter()
+                .fold(next, |next, node| self.compile(node, next)),
+            AstNode::Chain(nodes) => nodes
+                .iter()
+                .rev()
+                .fold(next, |next, node| self.compile(node, next)),
+            AstNode::Alternation(branches) => {
+                let (last, rest) = branches.split_last().unwrap();
+                let last = self.compile(last, next);
+                rest.iter().rev().fold(last, |second, branch| {
+                    let first = self.compile(branch, next);
+                    self.push(Inst::Split { first, second })
+                })
+            }
+            AstNode::Quantifier(q, greedy, node) => {
+                let (min, max) = q.bounds();
+                self.compile_repeat(node, min, max, *greedy, next)
+            }
+            AstNode::Char(Char::Lit(c)) => {
+                let mut buf = [0; 4];
+                let bytes = c.encode_utf8(&mut buf).as_bytes();
+                let reverse = self.reverse;
+                let push = |next, &byte| self.push(Inst::Char { byte, next });
+                match reverse {
+                    true => bytes.iter().fold(next, push),
+                    false => bytes.iter().rev().fold(next, push),
+                }
+            }
+            AstNode::Char(Char::Dot | Char::Escape(_) | Char::Byte(_)) => {
+                unreachable!("resolved by the parser")
+            }
+            AstNode::CharClass(class) => self.compile_class(class, next),
+            AstNode::ByteClass(class) => {
+                let ranges = class
+                    .matched_ranges()
+                    .into_iter()
+                    .take_while(|&(start, _)| start <= '\u{FF}')
+                    .map(|(start, end)| (start as u8, end.min('\u{FF}') as u8, next))
+                    .collect();
+                self.push(Inst::Class { ranges })
+            }
+            // Reversed, the text starts where it used to end
+            AstNode::StartAnchor | AstNode::EndAnchor => {
+                let look = match (node, self.reverse) {
+                    (AstNode::StartAnchor, false) | (AstNode::EndAnchor, true) => Look::StartText,
+                    _ => Look::EndText,
+                };
+                self.push(Inst::Assert { look, next })
+            }
+            AstNode::StartLineAnchor | AstNode::EndLineAnchor => {
+                let look = match (node, self.reverse) {
+                    (AstNode::StartLineAnchor, false) | (AstNode::EndLineAnchor, true) => {
+                        Look::StartLine
+                    }
+                    _ => Look::EndLine,
+                };
+                self.push(Inst::Assert { look, next })
+            }
+            AstNode::NonCapturingGroup(node) => self.compile(node, next),
+            AstNode::CaptureGroup(_, _, node) if self.reverse => self.compile(node, next),
+            AstNode::CaptureGroup(index, _, node) => {
+                let end = self.push(Inst::Save {
+                    slot: 2 * index + 1,
+                    next,
+                });
+                let body = self.compile(node, end);
+                self.push(Inst::Save {
+                    slot: 2 * index,
+                    next: body,
+                })
+            }
+        }
+    }
+
+    /// Expands `x{min,max}` into `min` copies of `x` followed by either a
+    /// loop or `max - min` nested optional copies.
+    fn compile_repeat(
+        &mut self,
+        node: &AstNode,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+        next: StateId,
+    ) -> StateId {
+        let mut rest = match max {
+            None => {
+                let split = self.push(Inst::Jump(0));
+                let body = self.compile(node, split);
+                self.insts[split] = self.split(body, next, greedy);
+                split
+            }
+            Some(max) => (min..max).fold(next, |rest, _| {
+                let body = self.compile(node, rest);
+                let split = self.split(body, next, greedy);
+                self.push(split)
+            }),
+        };
+
+        for _ in 0..min {
+            rest = self.compile(node, rest);
+        }
+        rest
+    }
+
+    /// A split trying `body` first when greedy and `skip` first otherwise.
+    fn split(&self, body: StateId, skip: StateId, greedy: bool) -> Inst {
+        match greedy {
+            true => Inst::Split {
+                first: body,
+                second: skip,
+            },
+            false => Inst::Split {
+                first: skip,
+                second: body,
+            },
+        }
+    }
+
+    /// Compiles a class into a trie of byte ranges over the UTF-8 encodings
+    /// of its chars.
+    fn compile_class(&mut self, class: &CharClass, next: StateId) -> StateId {
+        if self.reverse {
+            return self.compile_class_reverse(class, next);
+        }
+
+        let mut trie = Trie::default();
+        for (start, end) in class.matched_ranges() {
+            for sequence in utf8::sequences(start, end) {
+                trie.insert(&sequence);
+            }
+        }
+        self.compile_trie(&trie, next)
+    }
+
+    fn compile_trie(&mut self, trie: &Trie, next: StateId) -> StateId {
+        let ranges = trie
+            .children
+            .iter()
+            .map(|(start, end, child)| match child.children.is_empty() {
+                true => (*start, *end, next),
+                false => (*start, *end, self.compile_trie(child, next)),
+            })
+            .collect();
+        self.push(Inst::Class { ranges })
+    }
+}
+
+impl Compiler {
+    /// Compiles a class into an alternation of the reversed UTF-8 byte
+    /// range sequences of its chars. Reversed sequences may start with
+    /// overlapping ranges, so they cannot share a trie.
+    fn compile_class_reverse(&mut self, class: &CharClass, next: StateId) -> StateId {
+        let sequences: Vec<_> = class
+            .matched_ranges()
+            .into_iter()
+            .flat_map(|(start, end)| utf8::sequences(start, end))
+            .collect();
+
+        let alternatives: Vec<_> = sequences
+            .iter()
+            .map(|sequence| {
+                sequence.iter().fold(next, |next, &(start, end)| {
+                    self.push(Inst::Class {
+                        ranges: [(start, end, next)].into(),
+                    })
+                })
+            })
+            .collect();
+
+        match alternatives
+            .into_iter()
+            .rev()
+            .reduce(|second, first| self.push(Inst::Split { first, second }))
+        {
+            Some(start) => start,
+            // An empty class never matches
+            None => self.push(Inst::Class { ranges: [].into() }),
+        }
+    }
+}
+
+/// Byte range sequences sharing their common prefixes.
+#[derive(Default)]
+struct Trie {
+    children: Vec<(u8, u8, Trie)>,
+}
+
+impl Trie {
+    fn insert(&mut self, sequence: &[(u8, u8)]) {
+        let Some((&(start, end), rest)) = sequence.split_first() else {
+            return;
+        };
+
+        // Sequences come in order and first ranges are equal or disjoint,
+        // so a shared prefix can only be with the last child
+        let child = match self.children.last_mut() {
+            Some((s, e, child)) if (*s, *e) == (start, end) => child,
+            _ => {
+                self.children.push((start, end, Trie::default()));
+                &mut self.children.last_mut().unwrap().2
+            }
+        };
+        child.insert(rest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::tokenizer::tokenize;
+
+    fn compile(pattern: &str) -> Program {
+        let ast = parse(tokenize(pattern.into()).unwrap()).unwrap();
+        Program::compile(&ast, DEFAULT_SIZE_LIMIT).unwrap()
+    }
+
+    #[test]
+    fn test_compile_literal() {
+        let program = compile("aé");
+
+        assert_eq!(
+            program.insts,
+            vec![
+                Inst::Match,
+                Inst::Save { slot: 1, next: 0 },
+                Inst::Char {
+                    byte: 0xA9,
+                    next: 1
+                },
+                Inst::Char {
+                    byte: 0xC3,
+                    next: 2
+                },
+                Inst::Char {
+                    byte: b'a',
+                    next: 3
+                },
+                Inst::Save { slot: 0, next: 4 },
+                Inst::Split {
+                    first: 5,
+                    second: 7
+                },
+                Inst::Class {
+                    ranges: [(0x00, 0xFF, 6)].into()
+                },
+            ]
+        );
+        assert_eq!(program.start, 5);
+        assert_eq!(program.start_unanchored, 6);
+        assert_eq!(program.slots_len, 2);
+    }
+
+    #[test]
+    fn test_compile_repeat() {
+        let program = compile("a*?");
+
+        assert_eq!(
+            program.insts[..5],
+            [
+                Inst::Match,
+                Inst::Save { slot: 1, next: 0 },
+                Inst::Split {
+                    first: 1,
+                    second: 3
+                },
+                Inst::Char {
+                    byte: b'a',
+                    next: 2
+                },
+                Inst::Save { slot: 0, next: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_class_shares_prefixes() {
+        let program = compile("[é-ê]");
+
+        assert_eq!(
+            program.insts[2..4],
+            [
+                Inst::Class {
+                    ranges: [(0xA9, 0xAA, 1)].into()
+                },
+                Inst::Class {
+                    ranges: [(0xC3, 0xC3, 2)].into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compile_reverse() {
+        let ast = parse(tokenize("^(a)é$".into()).unwrap()).unwrap();
+        let program = Program::compile_reverse(&ast, DEFAULT_SIZE_LIMIT).unwrap();
+
+        assert_eq!(
+            program.insts[..6],
+            [
+                Inst::Match,
+                Inst::Assert {
+                    look: Look::EndText,
+                    next: 0
+                },
+                Inst::Char {
+                    byte: b'a',
+                    next: 1
+                },
+                Inst::Char {
+                    byte: 0xC3,
+                    next: 2
+                },
+                Inst::Char {
+                    byte: 0xA9,
+                    next: 3
+                },
+                Inst::Assert {
+                    look: Look::StartText,
+                    next: 4
+                },
+            ]
+        );
+        assert_eq!(program.start, 5);
+        assert_eq!(program.slots_len, 0);
+    }
+
+    #[test]
+    fn test_compile_set() {
+        let asts = ["a", "b"].map(|p| parse(tokenize(p.into()).unwrap()).unwrap());
+        let program = Program::compile_set(&asts, DEFAULT_SIZE_LIMIT).unwrap();
+
+        assert_eq!(
+            program.insts[..5],
+            [
+                Inst::Match,
+                Inst::Char {
+                    byte: b'a',
+                    next: 0
+                },
+                Inst::Match,
+                Inst::Char {
+                    byte: b'b',
+                    next: 2
+                },
+                Inst::Split {
+                    first: 1,
+                    second: 3
+                },
+            ]
+        );
+        assert_eq!(program.start, 4);
+        assert_eq!(program.matches, [0, 2]);
+        assert_eq!(program.pattern_of(2), Some(1));
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let ast = parse(tokenize("(?:a{1000}){1000}".into()).unwrap()).unwrap();
+        assert!(matches!(
+            Program::compile(&ast, DEFAULT_SIZE_LIMIT),
+            Err(ParseError::SizeLimitExceeded(DEFAULT_SIZE_LIMIT))
+        ));
+        assert!(matches!(
+            Program::compile_reverse(&ast, 1000),
+            Err(ParseError::SizeLimitExceeded(1000))
+        ));
+
+        let ast = parse(tokenize("a{100}".into()).unwrap()).unwrap();
+        let size = 103 * std::mem::size_of::<Inst>();
+        assert!(Program::compile(&ast, size).is_ok());
+        assert!(Program::compile(&ast, size - 1).is_err());
+    }
+}
@@ -1,143 +1,577 @@
-use crate::tokenizer::{Anchor, Char, Quantifier, Token};
+use crate::class::CharClass;
+use crate::tokenizer::{Anchor, Char, Flags, Quantifier, Token};
 
 use super::errors::ParseError;
 
+/// Maximum number of nested groups accepted by default.
+pub const DEFAULT_NEST_LIMIT: usize = 250;
+
+/// Maximum count accepted by default in a `{n,m}` repetition. Repetitions
+/// are expanded when compiling, so this caps the program size per
+/// repetition.
+pub const DEFAULT_REPETITION_LIMIT: u32 = 1000;
+
 #[derive(Debug, PartialEq)]
 pub enum AstNode {
     Chain(Box<[AstNode]>),
-    Quantifier(Quantifier, Box<AstNode>),
+    Alternation(Box<[AstNode]>),
+    /// Repeats the node, preferring the most repetitions when greedy and
+    /// the fewest otherwise
+    Quantifier(Quantifier, bool, Box<AstNode>),
     Char(Char),
-    CharClass(Box<[Char]>),
-    StartAnchor(Box<AstNode>),
+    CharClass(CharClass),
+    /// Matches a single byte whose value, read as a char, is in the class,
+    /// whether or not it is part of a valid UTF-8 sequence
+    ByteClass(CharClass),
+    StartAnchor,
     EndAnchor,
-    CaptureGroup(Box<[AstNode]>),
+    /// `^` in multi-line mode, also matching right after a `\n`
+    StartLineAnchor,
+    /// `$` in multi-line mode, also matching right before a `\n`
+    EndLineAnchor,
+    CaptureGroup(usize, Option<String>, Box<AstNode>),
+    NonCapturingGroup(Box<AstNode>),
+}
+
+impl AstNode {
+    /// Number of capture groups in the tree, not counting the implicit
+    /// group 0 around the whole pattern.
+    pub fn captures_len(&self) -> usize {
+        match self {
+            AstNode::Chain(nodes) | AstNode::Alternation(nodes) => {
+                nodes.iter().map(AstNode::captures_len).sum()
+            }
+            AstNode::Quantifier(_, _, node) | AstNode::NonCapturingGroup(node) => {
+                node.captures_len()
+            }
+            AstNode::CaptureGroup(_, _, node) => 1 + node.captures_len(),
+            AstNode::Char(_)
+            | AstNode::CharClass(_)
+            | AstNode::ByteClass(_)
+            | AstNode::StartAnchor
+            | AstNode::EndAnchor
+            | AstNode::StartLineAnchor
+            | AstNode::EndLineAnchor => 0,
+        }
+    }
+
+    /// Names of the capture groups indexed by group number, `None` for
+    /// unnamed groups and for the implicit group 0.
+    pub fn capture_names(&self) -> Vec<Option<String>> {
+        let mut names = vec![None; self.captures_len() + 1];
+        self.collect_capture_names(&mut names);
+        names
+    }
+
+    fn collect_capture_names(&self, names: &mut [Option<String>]) {
+        match self {
+            AstNode::Chain(nodes) | AstNode::Alternation(nodes) => {
+                for node in nodes.iter() {
+                    node.collect_capture_names(names);
+                }
+            }
+            AstNode::Quantifier(_, _, node) | AstNode::NonCapturingGroup(node) => {
+                node.collect_capture_names(names)
+            }
+            AstNode::CaptureGroup(index, name, node) => {
+                names[*index].clone_from(name);
+                node.collect_capture_names(names);
+            }
+            AstNode::Char(_)
+            | AstNode::CharClass(_)
+            | AstNode::ByteClass(_)
+            | AstNode::StartAnchor
+            | AstNode::EndAnchor
+            | AstNode::StartLineAnchor
+            | AstNode::EndLineAnchor => {}
+        }
+    }
 }
 
 pub fn parse(tokens: Vec<Token>) -> Result<AstNode, ParseError> {
-    let mut chain: Vec<AstNode> = vec![];
-    let mut has_start_anchor = false;
-    let mut has_end_anchor = false;
-    let mut tokens = tokens.iter().peekable();
-
-    if let Some(Token::Anchor {
-        pos: _,
-        val: Anchor::Start,
-    }) = tokens.peek()
-    {
-        tokens.next();
-        has_start_anchor = true;
-    }
-
-    while let Some(tok) = tokens.next() {
-        if has_end_anchor {
-            return Err(ParseError::UnexpectedToken(
+    Parser::new(&tokens).parse()
+}
+
+/// Recursive-descent parser following the grammar:
+///
+/// ```text
+/// expression    := alternation
+/// alternation   := concatenation ('|' concatenation)*
+/// concatenation := repetition*
+/// repetition    := atom quantifier?
+/// quantifier    := ('*' | '+' | '?' | '{n}' | '{n,}' | '{n,m}') '?'?
+/// atom          := char | class | anchor | group
+/// group         := ('(' | '(?:' | '(?flags:' | '(?P<name>' | '(?<name>') alternation ')'
+/// ```
+///
+/// A `(?flags)` item may also appear anywhere in a concatenation, changing
+/// the flags until the end of the enclosing group.
+///
+/// Groups are the only recursive rule, so limiting how deeply they nest
+/// bounds the stack used by the parser.
+pub struct Parser<'a> {
+    tokens: std::slice::Iter<'a, Token>,
+    depth: usize,
+    nest_limit: usize,
+    repetition_limit: u32,
+    captures_len: usize,
+    capture_names: std::collections::HashSet<String>,
+    modes: Modes,
+    allow_invalid_utf8: bool,
+}
+
+/// The flags in effect at the current token.
+#[derive(Debug, Clone, Copy)]
+struct Modes {
+    unicode: bool,
+    case_insensitive: bool,
+    multi_line: bool,
+    dot_matches_new_line: bool,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(tokens: &'a [Token]) -> Self {
+        Self {
+            tokens: tokens.iter(),
+            depth: 0,
+            nest_limit: DEFAULT_NEST_LIMIT,
+            repetition_limit: DEFAULT_REPETITION_LIMIT,
+            captures_len: 0,
+            capture_names: std::collections::HashSet::new(),
+            modes: Modes {
+                unicode: true,
+                case_insensitive: false,
+                multi_line: false,
+                dot_matches_new_line: false,
+            },
+            allow_invalid_utf8: false,
+        }
+    }
+
+    pub fn nest_limit(mut self, limit: usize) -> Self {
+        self.nest_limit = limit;
+        self
+    }
+
+    pub fn repetition_limit(mut self, limit: u32) -> Self {
+        self.repetition_limit = limit;
+        self
+    }
+
+    /// Initial state of the `u` flag.
+    pub fn unicode(mut self, yes: bool) -> Self {
+        self.modes.unicode = yes;
+        self
+    }
+
+    /// Initial state of the `i` flag.
+    pub fn case_insensitive(mut self, yes: bool) -> Self {
+        self.modes.case_insensitive = yes;
+        self
+    }
+
+    /// Initial state of the `m` flag.
+    pub fn multi_line(mut self, yes: bool) -> Self {
+        self.modes.multi_line = yes;
+        self
+    }
+
+    /// Initial state of the `s` flag.
+    pub fn dot_matches_new_line(mut self, yes: bool) -> Self {
+        self.modes.dot_matches_new_line = yes;
+        self
+    }
+
+    /// Lets `(?-u)` turn `.`, `\xHH` escapes and classes into matchers of
+    /// single bytes, which can match invalid UTF-8.
+    pub fn allow_invalid_utf8(mut self, yes: bool) -> Self {
+        self.allow_invalid_utf8 = yes;
+        self
+    }
+
+    fn byte_mode(&self) -> bool {
+        self.allow_invalid_utf8 && !self.modes.unicode
+    }
+
+    /// A single char, or the class of its cases when ignoring case.
+    fn literal(&self, c: char) -> AstNode {
+        if !self.modes.case_insensitive {
+            return AstNode::Char(Char::Lit(c));
+        }
+        let single = CharClass::new([(c, c)], false);
+        match single.case_fold(self.modes.unicode) {
+            class if class == single => AstNode::Char(Char::Lit(c)),
+            class if self.byte_mode() => AstNode::ByteClass(class),
+            class => AstNode::CharClass(class),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.as_slice().first()
+    }
+
+    fn next_if(&mut self, func: impl FnOnce(&Token) -> bool) -> Option<&'a Token> {
+        match self.peek() {
+            Some(tok) if func(tok) => self.tokens.next(),
+            _ => None,
+        }
+    }
+
+    pub fn parse(mut self) -> Result<AstNode, ParseError> {
+        let ast = self.parse_alternation()?;
+
+        // `parse_alternation` only stops early on an unbalanced `)`
+        match self.tokens.next() {
+            Some(tok) => Err(ParseError::UnexpectedToken(
+                tok.clone(),
+                "Group end anchor only allowed after group start anchor".into(),
+            )),
+            None => Ok(ast),
+        }
+    }
+
+    fn parse_alternation(&mut self) -> Result<AstNode, ParseError> {
+        let mut branches = vec![self.parse_concatenation()?];
+
+        while self
+            .next_if(|tok| {
+                matches!(
+                    tok,
+                    Token::Anchor {
+                        val: Anchor::Alternation,
+                        ..
+                    }
+                )
+            })
+            .is_some()
+        {
+            branches.push(self.parse_concatenation()?);
+        }
+
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(AstNode::Alternation(branches.into()))
+        }
+    }
+
+    fn parse_concatenation(&mut self) -> Result<AstNode, ParseError> {
+        let mut chain = vec![];
+
+        while let Some(tok) = self.peek() {
+            match tok {
                 Token::Anchor {
-                    pos: tok.pos(),
-                    val: Anchor::End,
-                },
-                "End anchor only allowed at the end of the string".into(),
+                    val: Anchor::Alternation | Anchor::GroupEnd,
+                    ..
+                } => break,
+                Token::Anchor {
+                    val: Anchor::SetFlags(flags),
+                    ..
+                } => {
+                    self.tokens.next();
+                    self.apply_flags(*flags);
+                }
+                _ => chain.push(self.parse_repetition()?),
+            }
+        }
+
+        Ok(AstNode::Chain(chain.into()))
+    }
+
+    fn parse_repetition(&mut self) -> Result<AstNode, ParseError> {
+        let node = self.parse_atom()?;
+
+        let Some(&Token::Quantifier { val, greedy, .. }) = self.peek() else {
+            return Ok(node);
+        };
+        let tok = self.tokens.next().unwrap().clone();
+
+        if matches!(
+            node,
+            AstNode::StartAnchor
+                | AstNode::EndAnchor
+                | AstNode::StartLineAnchor
+                | AstNode::EndLineAnchor
+        ) {
+            return Err(ParseError::UnexpectedToken(
+                tok,
+                "Quantifier cannot be applied to an anchor".into(),
+            ));
+        }
+        if let Some(tok) = self.next_if(|tok| matches!(tok, Token::Quantifier { .. })) {
+            return Err(ParseError::UnexpectedToken(
+                tok.clone(),
+                "Quantifier cannot follow another quantifier".into(),
             ));
         }
+        if let Quantifier::Range { min, max } = val {
+            if min.max(max.unwrap_or(0)) > self.repetition_limit {
+                return Err(ParseError::RepetitionLimitExceeded(
+                    tok.pos(),
+                    self.repetition_limit,
+                ));
+            }
+        }
 
-        let node: AstNode = match tok {
-            Token::Char { val, .. } => AstNode::Char(*val),
-            Token::Anchor {
-                val: Anchor::CharClassStart { .. },
+        Ok(AstNode::Quantifier(val, greedy, Box::new(node)))
+    }
+
+    fn parse_atom(&mut self) -> Result<AstNode, ParseError> {
+        let Some(tok) = self.tokens.next() else {
+            return Err(ParseError::UnexpectedEnd("an expression".into()));
+        };
+
+        match tok {
+            Token::Char {
+                val: Char::Escape(escape),
                 ..
-            } => {
-                let mut chars = vec![];
-                for tok in tokens.by_ref() {
-                    match tok {
-                        Token::Char { val, .. } => chars.push(*val),
-                        Token::Anchor {
-                            val: Anchor::CharClassEnd { .. },
-                            ..
-                        } => break,
-                        _ => {
-                            return Err(ParseError::UnexpectedToken(
-                                *tok,
-                                "Should have found a char literal".into(),
-                            ))
-                        }
-                    }
-                }
-                AstNode::CharClass(chars.into())
+            } => Ok(match CharClass::perl(*escape, self.modes.unicode) {
+                Some(class) if self.byte_mode() => AstNode::ByteClass(class),
+                Some(class) => AstNode::CharClass(class),
+                None => self.literal(escape.as_char().unwrap()),
+            }),
+            Token::Char {
+                val: Char::Byte(b), ..
+            } => Ok(if self.byte_mode() && !b.is_ascii() {
+                AstNode::ByteClass(CharClass::new([(*b as char, *b as char)], false))
+            } else {
+                self.literal(*b as char)
+            }),
+            Token::Char { val: Char::Dot, .. } => {
+                let class = match self.modes.dot_matches_new_line {
+                    true => CharClass::new([], true),
+                    false => CharClass::new([('\n', '\n')], true),
+                };
+                Ok(match self.byte_mode() {
+                    true => AstNode::ByteClass(class),
+                    false => AstNode::CharClass(class),
+                })
             }
+            Token::Char {
+                val: Char::Lit(c), ..
+            } => Ok(self.literal(*c)),
             Token::Anchor {
-                val: Anchor::CharClassEnd { .. },
+                val: Anchor::CharClassStart,
                 ..
-            } => {
-                return Err(ParseError::UnexpectedToken(
-                    *tok,
-                    "Char class end anchor only allowed after char class start anchor".into(),
-                ))
-            }
+            } => self.parse_char_class(),
             Token::Anchor {
-                val: Anchor::GroupStart { .. },
+                val: Anchor::GroupStart,
                 ..
-            } => {
-                let mut group = vec![];
-                for tok in tokens.by_ref() {
-                    match tok {
-                        Token::Char { val, .. } => group.push(AstNode::Char(*val)),
-                        Token::Anchor {
-                            val: Anchor::GroupEnd { .. },
-                            ..
-                        } => break,
-                        _ => {
-                            return Err(ParseError::UnexpectedToken(
-                                *tok,
-                                "Should have found a char literal".into(),
-                            ))
-                        }
-                    }
-                }
-                AstNode::CaptureGroup(group.into())
-            }
+            } => self.parse_capture_group(tok, None),
+            Token::Anchor {
+                val: Anchor::NonCapturingGroupStart,
+                ..
+            } => Ok(AstNode::NonCapturingGroup(Box::new(self.parse_group(tok)?))),
             Token::Anchor {
-                val: Anchor::GroupEnd { .. },
+                val: Anchor::FlagGroupStart(_),
+                ..
+            } => Ok(AstNode::NonCapturingGroup(Box::new(self.parse_group(tok)?))),
+            Token::Anchor {
+                val: Anchor::NamedGroupStart(name),
                 ..
             } => {
-                return Err(ParseError::UnexpectedToken(
-                    *tok,
-                    "Group end anchor only allowed after group start anchor".into(),
-                ))
+                if !self.capture_names.insert(name.clone()) {
+                    return Err(ParseError::BadGroupName(
+                        tok.pos(),
+                        format!("Duplicate group name: {}", name),
+                    ));
+                }
+                self.parse_capture_group(tok, Some(name.clone()))
             }
             Token::Anchor {
                 val: Anchor::Start, ..
-            } => {
-                return Err(ParseError::UnexpectedToken(
-                    *tok,
-                    "Start anchor only allowed at the start of the string".into(),
-                ))
-            }
+            } => Ok(match self.modes.multi_line {
+                true => AstNode::StartLineAnchor,
+                false => AstNode::StartAnchor,
+            }),
             Token::Anchor {
                 val: Anchor::End, ..
-            } => {
-                has_end_anchor = true;
-                AstNode::EndAnchor
-            }
-            _ => {
-                return Err(ParseError::UnexpectedToken(
-                    *tok,
-                    "Should have found a char literal".into(),
-                ))
-            }
-        };
+            } => Ok(match self.modes.multi_line {
+                true => AstNode::EndLineAnchor,
+                false => AstNode::EndAnchor,
+            }),
+            Token::Anchor {
+                val: Anchor::CharClassEnd,
+                ..
+            } => Err(ParseError::UnexpectedToken(
+                tok.clone(),
+                "Char class end anchor only allowed after char class start anchor".into(),
+            )),
+            Token::Quantifier { .. } => Err(ParseError::UnexpectedToken(
+                tok.clone(),
+                "Quantifier must follow a char, a char class or a group".into(),
+            )),
+            Token::Anchor {
+                val: Anchor::GroupEnd | Anchor::Alternation | Anchor::SetFlags(_),
+                ..
+            } => unreachable!("handled by the enclosing concatenation"),
+            Token::Anchor {
+                val: Anchor::CharClassNegate | Anchor::CharClassRange,
+                ..
+            } => unreachable!("only produced inside char classes"),
+        }
+    }
+
+    fn parse_char_class(&mut self) -> Result<AstNode, ParseError> {
+        let negated = self
+            .next_if(|tok| {
+                matches!(
+                    tok,
+                    Token::Anchor {
+                        val: Anchor::CharClassNegate,
+                        ..
+                    }
+                )
+            })
+            .is_some();
+        let mut ranges = vec![];
+
+        while let Some(tok) = self.tokens.next() {
+            let start = match tok {
+                Token::Anchor {
+                    val: Anchor::CharClassEnd,
+                    ..
+                } => {
+                    let mut class = CharClass::new(ranges, negated);
+                    if self.modes.case_insensitive {
+                        class = class.case_fold(self.modes.unicode);
+                    }
+                    return Ok(match self.byte_mode() {
+                        true => AstNode::ByteClass(class),
+                        false => AstNode::CharClass(class),
+                    });
+                }
+                // A `-` that does not sit between two chars is literal
+                Token::Anchor {
+                    val: Anchor::CharClassRange,
+                    ..
+                } => '-',
+                Token::Char {
+                    val: Char::Escape(escape),
+                    ..
+                } if escape.as_char().is_none() => {
+                    let class = CharClass::perl(*escape, self.modes.unicode).unwrap();
+                    ranges.extend(class.matched_ranges());
+                    continue;
+                }
+                Token::Char {
+                    val: Char::Lit(c), ..
+                } if self.byte_mode() && !c.is_ascii() => {
+                    return Err(ParseError::UnexpectedToken(
+                        tok.clone(),
+                        "Should have found an ASCII char or a byte escape in a byte class".into(),
+                    ))
+                }
+                tok => match self.class_char(tok) {
+                    Some(c) => c,
+                    None => {
+                        return Err(ParseError::UnexpectedToken(
+                            tok.clone(),
+                            "Should have found a char literal".into(),
+                        ))
+                    }
+                },
+            };
 
-        match tokens.peek() {
-            Some(&Token::Quantifier { val, .. }) => {
-                tokens.next();
-                chain.push(AstNode::Quantifier(*val, Box::new(node)))
+            let end = match self.tokens.as_slice() {
+                [Token::Anchor {
+                    val: Anchor::CharClassRange,
+                    ..
+                }, end, ..]
+                    if !matches!(
+                        tok,
+                        Token::Anchor {
+                            val: Anchor::CharClassRange,
+                            ..
+                        }
+                    ) && self.class_char(end).is_some() =>
+                {
+                    self.tokens.nth(1);
+                    self.class_char(end).unwrap()
+                }
+                _ => start,
+            };
+
+            if start > end {
+                return Err(ParseError::BadCharRange(
+                    tok.pos(),
+                    format!("{}-{} is a reversed range", start, end),
+                ));
             }
-            _ => chain.push(node),
+            ranges.push((start, end));
         }
+
+        Err(ParseError::UnexpectedEnd("a char class end anchor".into()))
     }
 
-    if has_start_anchor {
-        Ok(AstNode::StartAnchor(Box::new(AstNode::Chain(chain.into()))))
-    } else {
-        Ok(AstNode::Chain(chain.into()))
+    /// The single char a token stands for inside a char class, if any. In
+    /// byte mode only ASCII chars and byte escapes stand for a byte.
+    fn class_char(&self, tok: &Token) -> Option<char> {
+        match tok {
+            Token::Char {
+                val: Char::Lit(c), ..
+            } if !self.byte_mode() || c.is_ascii() => Some(*c),
+            Token::Char {
+                val: Char::Escape(escape),
+                ..
+            } => escape.as_char(),
+            Token::Char {
+                val: Char::Byte(b), ..
+            } => Some(*b as char),
+            _ => None,
+        }
+    }
+
+    fn parse_capture_group(
+        &mut self,
+        start: &Token,
+        name: Option<String>,
+    ) -> Result<AstNode, ParseError> {
+        // Groups are numbered in the order of their opening paren
+        self.captures_len += 1;
+        let index = self.captures_len;
+
+        let inner = self.parse_group(start)?;
+        Ok(AstNode::CaptureGroup(index, name, Box::new(inner)))
+    }
+
+    fn apply_flags(&mut self, flags: Flags) {
+        let modes = &mut self.modes;
+        modes.unicode = flags.unicode.unwrap_or(modes.unicode);
+        modes.case_insensitive = flags.case_insensitive.unwrap_or(modes.case_insensitive);
+        modes.multi_line = flags.multi_line.unwrap_or(modes.multi_line);
+        modes.dot_matches_new_line = flags
+            .dot_matches_new_line
+            .unwrap_or(modes.dot_matches_new_line);
+    }
+
+    /// Parses the contents of a group up to and including its closing paren.
+    /// Flags set inside the group do not outlive it.
+    fn parse_group(&mut self, start: &Token) -> Result<AstNode, ParseError> {
+        if self.depth >= self.nest_limit {
+            return Err(ParseError::NestLimitExceeded(start.pos(), self.nest_limit));
+        }
+
+        let modes = self.modes;
+        if let Token::Anchor {
+            val: Anchor::FlagGroupStart(flags),
+            ..
+        } = start
+        {
+            self.apply_flags(*flags);
+        }
+        self.depth += 1;
+        let inner = self.parse_alternation()?;
+        self.depth -= 1;
+        self.modes = modes;
+
+        match self.tokens.next() {
+            Some(Token::Anchor {
+                val: Anchor::GroupEnd,
+                ..
+            }) => Ok(inner),
+            _ => Err(ParseError::UnexpectedEnd("a group end anchor".into())),
+        }
     }
 }
 
@@ -150,35 +584,392 @@ mod tests {
     fn test_parse_char_class() {
         let tokens = tokenize("[abc]".into()).unwrap();
 
+        let expected_ast =
+            AstNode::Chain(vec![AstNode::CharClass(CharClass::new([('a', 'c')], false))].into());
+
+        let ast = parse(tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[test]
+    fn test_capture_group() {
+        let tokens = tokenize("(abc)".into()).unwrap();
+
         let expected_ast = AstNode::Chain(
-            vec![AstNode::CharClass(
-                vec![Char::Lit('a'), Char::Lit('b'), Char::Lit('c')].into(),
+            vec![AstNode::CaptureGroup(
+                1,
+                None,
+                Box::new(AstNode::Chain(
+                    vec![
+                        AstNode::Char(Char::Lit('a')),
+                        AstNode::Char(Char::Lit('b')),
+                        AstNode::Char(Char::Lit('c')),
+                    ]
+                    .into(),
+                )),
             )]
             .into(),
         );
 
         let ast = parse(tokens).unwrap();
+        // println!("{ast:#?}");
         assert_eq!(ast, expected_ast);
     }
 
     #[test]
-    fn test_capture_group() {
-        let tokens = tokenize("(abc)".into()).unwrap();
+    fn test_alternation() {
+        let tokens = tokenize("ab|c|".into()).unwrap();
+
+        let expected_ast = AstNode::Alternation(
+            vec![
+                AstNode::Chain(
+                    vec![AstNode::Char(Char::Lit('a')), AstNode::Char(Char::Lit('b'))].into(),
+                ),
+                AstNode::Chain(vec![AstNode::Char(Char::Lit('c'))].into()),
+                AstNode::Chain(vec![].into()),
+            ]
+            .into(),
+        );
+
+        let ast = parse(tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[test]
+    fn test_alternation_in_group() {
+        let tokens = tokenize("(a|b)".into()).unwrap();
 
         let expected_ast = AstNode::Chain(
             vec![AstNode::CaptureGroup(
-                vec![
-                    AstNode::Char(Char::Lit('a')),
-                    AstNode::Char(Char::Lit('b')),
-                    AstNode::Char(Char::Lit('c')),
-                ]
-                .into(),
+                1,
+                None,
+                Box::new(AstNode::Alternation(
+                    vec![
+                        AstNode::Chain(vec![AstNode::Char(Char::Lit('a'))].into()),
+                        AstNode::Chain(vec![AstNode::Char(Char::Lit('b'))].into()),
+                    ]
+                    .into(),
+                )),
             )]
             .into(),
         );
 
         let ast = parse(tokens).unwrap();
-        // println!("{ast:#?}");
         assert_eq!(ast, expected_ast);
     }
+
+    #[test]
+    fn test_nested_groups() {
+        let tokens = tokenize("(a(b)c)".into()).unwrap();
+
+        let expected_ast = AstNode::Chain(
+            vec![AstNode::CaptureGroup(
+                1,
+                None,
+                Box::new(AstNode::Chain(
+                    vec![
+                        AstNode::Char(Char::Lit('a')),
+                        AstNode::CaptureGroup(
+                            2,
+                            None,
+                            Box::new(AstNode::Chain(vec![AstNode::Char(Char::Lit('b'))].into())),
+                        ),
+                        AstNode::Char(Char::Lit('c')),
+                    ]
+                    .into(),
+                )),
+            )]
+            .into(),
+        );
+
+        let ast = parse(tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[test]
+    fn test_group_contents() {
+        let tokens = tokenize("([ab]c+|^$)*".into()).unwrap();
+
+        let expected_ast = AstNode::Chain(
+            vec![AstNode::Quantifier(
+                Quantifier::Any,
+                true,
+                Box::new(AstNode::CaptureGroup(
+                    1,
+                    None,
+                    Box::new(AstNode::Alternation(
+                        vec![
+                            AstNode::Chain(
+                                vec![
+                                    AstNode::CharClass(CharClass::new([('a', 'b')], false)),
+                                    AstNode::Quantifier(
+                                        Quantifier::Many,
+                                        true,
+                                        Box::new(AstNode::Char(Char::Lit('c'))),
+                                    ),
+                                ]
+                                .into(),
+                            ),
+                            AstNode::Chain(vec![AstNode::StartAnchor, AstNode::EndAnchor].into()),
+                        ]
+                        .into(),
+                    )),
+                )),
+            )]
+            .into(),
+        );
+
+        let ast = parse(tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[test]
+    fn test_unbalanced_groups() {
+        parse(tokenize("(ab".into()).unwrap()).unwrap_err();
+        parse(tokenize("ab)".into()).unwrap()).unwrap_err();
+        parse(tokenize("((a)".into()).unwrap()).unwrap_err();
+        parse(tokenize("[ab".into()).unwrap()).unwrap_err();
+    }
+
+    #[test]
+    fn test_misplaced_quantifiers() {
+        parse(tokenize("*a".into()).unwrap()).unwrap_err();
+        parse(tokenize("a**".into()).unwrap()).unwrap_err();
+        parse(tokenize("(*a)".into()).unwrap()).unwrap_err();
+        parse(tokenize("a|+".into()).unwrap()).unwrap_err();
+        parse(tokenize("^*".into()).unwrap()).unwrap_err();
+    }
+
+    #[test]
+    fn test_nest_limit() {
+        let tokens = tokenize("((a))".into()).unwrap();
+        Parser::new(&tokens).nest_limit(2).parse().unwrap();
+
+        let err = Parser::new(&tokens).nest_limit(1).parse().unwrap_err();
+        assert!(matches!(err, ParseError::NestLimitExceeded(1, 1)));
+
+        let deep = format!("{}a{}", "(".repeat(100_000), ")".repeat(100_000));
+        let tokens = tokenize(deep).unwrap();
+        let err = parse(tokens).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::NestLimitExceeded(DEFAULT_NEST_LIMIT, DEFAULT_NEST_LIMIT)
+        ));
+    }
+
+    #[test]
+    fn test_named_capture_group() {
+        let tokens = tokenize("(?P<a>x)(y)(?<b>z)".into()).unwrap();
+        let ast = parse(tokens).unwrap();
+
+        let expected_ast = AstNode::Chain(
+            vec![
+                AstNode::CaptureGroup(
+                    1,
+                    Some("a".into()),
+                    Box::new(AstNode::Chain(vec![AstNode::Char(Char::Lit('x'))].into())),
+                ),
+                AstNode::CaptureGroup(
+                    2,
+                    None,
+                    Box::new(AstNode::Chain(vec![AstNode::Char(Char::Lit('y'))].into())),
+                ),
+                AstNode::CaptureGroup(
+                    3,
+                    Some("b".into()),
+                    Box::new(AstNode::Chain(vec![AstNode::Char(Char::Lit('z'))].into())),
+                ),
+            ]
+            .into(),
+        );
+
+        assert_eq!(ast, expected_ast);
+        assert_eq!(
+            ast.capture_names(),
+            vec![None, Some("a".into()), None, Some("b".into())]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_group_name() {
+        let tokens = tokenize("(?P<a>x)|(?<a>y)".into()).unwrap();
+        let err = parse(tokens).unwrap_err();
+
+        assert!(matches!(err, ParseError::BadGroupName(9, _)));
+    }
+
+    #[test]
+    fn test_non_capturing_group() {
+        let tokens = tokenize("(?:a|b)*(c)".into()).unwrap();
+
+        let expected_ast = AstNode::Chain(
+            vec![
+                AstNode::Quantifier(
+                    Quantifier::Any,
+                    true,
+                    Box::new(AstNode::NonCapturingGroup(Box::new(AstNode::Alternation(
+                        vec![
+                            AstNode::Chain(vec![AstNode::Char(Char::Lit('a'))].into()),
+                            AstNode::Chain(vec![AstNode::Char(Char::Lit('b'))].into()),
+                        ]
+                        .into(),
+                    )))),
+                ),
+                AstNode::CaptureGroup(
+                    1,
+                    None,
+                    Box::new(AstNode::Chain(vec![AstNode::Char(Char::Lit('c'))].into())),
+                ),
+            ]
+            .into(),
+        );
+
+        let ast = parse(tokens).unwrap();
+        assert_eq!(ast, expected_ast);
+        assert_eq!(ast.captures_len(), 1);
+    }
+
+    #[test]
+    fn test_counted_repetition() {
+        let tokens = tokenize("a{2,3}".into()).unwrap();
+
+        let expected_ast = AstNode::Chain(
+            vec![AstNode::Quantifier(
+                Quantifier::Range {
+                    min: 2,
+                    max: Some(3),
+                },
+                true,
+                Box::new(AstNode::Char(Char::Lit('a'))),
+            )]
+            .into(),
+        );
+
+        assert_eq!(parse(tokens).unwrap(), expected_ast);
+    }
+
+    #[test]
+    fn test_repetition_limit() {
+        let tokens = tokenize("a{1000}b{2,}".into()).unwrap();
+        parse(tokens).unwrap();
+
+        for pattern in ["a{1001}", "a{1,1001}", "a{1001,}", "a{99999999999}"] {
+            let tokens = tokenize(pattern.into()).unwrap();
+            let err = parse(tokens).unwrap_err();
+            assert!(matches!(
+                err,
+                ParseError::RepetitionLimitExceeded(1, DEFAULT_REPETITION_LIMIT)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_lazy_quantifier() {
+        let tokens = tokenize("a+?b".into()).unwrap();
+
+        let expected_ast = AstNode::Chain(
+            vec![
+                AstNode::Quantifier(
+                    Quantifier::Many,
+                    false,
+                    Box::new(AstNode::Char(Char::Lit('a'))),
+                ),
+                AstNode::Char(Char::Lit('b')),
+            ]
+            .into(),
+        );
+
+        assert_eq!(parse(tokens).unwrap(), expected_ast);
+
+        // A lazy quantifier is still a single quantifier
+        parse(tokenize("a*?+".into()).unwrap()).unwrap_err();
+    }
+
+    #[test]
+    fn test_char_class_ranges() {
+        let ast = parse(tokenize("[^a-cx-]".into()).unwrap()).unwrap();
+        let expected_ast = AstNode::Chain(
+            vec![AstNode::CharClass(CharClass::new(
+                [('a', 'c'), ('x', 'x'), ('-', '-')],
+                true,
+            ))]
+            .into(),
+        );
+        assert_eq!(ast, expected_ast);
+
+        let ast = parse(tokenize("[-a]".into()).unwrap()).unwrap();
+        let expected_ast = AstNode::Chain(
+            vec![AstNode::CharClass(CharClass::new(
+                [('-', '-'), ('a', 'a')],
+                false,
+            ))]
+            .into(),
+        );
+        assert_eq!(ast, expected_ast);
+    }
+
+    #[test]
+    fn test_bad_char_range() {
+        let err = parse(tokenize("[z-a]".into()).unwrap()).unwrap_err();
+        assert!(matches!(err, ParseError::BadCharRange(1, _)));
+
+        let err = parse(tokenize("[a-c".into()).unwrap()).unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedEnd(_)));
+    }
+
+    #[test]
+    fn test_flags() {
+        let class = |ranges: &[(char, char)], negated| {
+            AstNode::CharClass(CharClass::new(ranges.iter().copied(), negated))
+        };
+
+        let ast = parse(tokenize("(?-s).(?s:.)".into()).unwrap()).unwrap();
+        let expected_ast = AstNode::Chain(
+            vec![
+                class(&[('\n', '\n')], true),
+                AstNode::NonCapturingGroup(Box::new(AstNode::Chain(vec![class(&[], true)].into()))),
+            ]
+            .into(),
+        );
+        assert_eq!(ast, expected_ast);
+
+        let ast = parse(tokenize("(?i)a1[b-c](?-i)d".into()).unwrap()).unwrap();
+        let expected_ast = AstNode::Chain(
+            vec![
+                class(&[('A', 'A'), ('a', 'a')], false),
+                AstNode::Char(Char::Lit('1')),
+                class(&[('B', 'C'), ('b', 'c')], false),
+                AstNode::Char(Char::Lit('d')),
+            ]
+            .into(),
+        );
+        assert_eq!(ast, expected_ast);
+
+        let tokens = tokenize("^(?-m:^$)$".into()).unwrap();
+        let ast = Parser::new(&tokens).multi_line(true).parse().unwrap();
+        let expected_ast = AstNode::Chain(
+            vec![
+                AstNode::StartLineAnchor,
+                AstNode::NonCapturingGroup(Box::new(AstNode::Chain(
+                    vec![AstNode::StartAnchor, AstNode::EndAnchor].into(),
+                ))),
+                AstNode::EndLineAnchor,
+            ]
+            .into(),
+        );
+        assert_eq!(ast, expected_ast);
+
+        let err = parse(tokenize("(?m)^*".into()).unwrap()).unwrap_err();
+        assert!(matches!(err, ParseError::UnexpectedToken(_, _)));
+    }
+
+    #[test]
+    fn test_custom_repetition_limit() {
+        let tokens = tokenize("a{10}b{11}".into()).unwrap();
+        let err = Parser::new(&tokens)
+            .repetition_limit(10)
+            .parse()
+            .unwrap_err();
+        assert!(matches!(err, ParseError::RepetitionLimitExceeded(6, 10)));
+    }
 }
@@ -0,0 +1,367 @@
+use crate::nfa::{Inst, Look, Program, StateId};
+
+/// Simulates every thread of a [`Program`] in lockstep, one byte at a time,
+/// so a search takes O(states × haystack) time whatever the pattern.
+///
+/// Threads are kept in priority order, and a thread reaching `Match` cuts
+/// off every thread of lower priority, which gives leftmost-first results.
+pub struct PikeVm<'p> {
+    program: &'p Program,
+    clist: Threads,
+    nlist: Threads,
+    stack: Vec<Frame>,
+}
+
+/// Work items of the epsilon closure, kept on an explicit stack so deep
+/// patterns cannot overflow the call stack.
+enum Frame {
+    Explore(StateId),
+    RestoreSlot(usize, Option<usize>),
+}
+
+/// A set of threads in insertion order, with the slots of each thread.
+struct Threads {
+    dense: Vec<StateId>,
+    sparse: Box<[usize]>,
+    slots: Box<[Option<usize>]>,
+    slots_len: usize,
+}
+
+impl Threads {
+    fn new(states: usize, slots_len: usize) -> Self {
+        Self {
+            dense: Vec::with_capacity(states),
+            sparse: vec![0; states].into(),
+            slots: vec![None; states * slots_len].into(),
+            slots_len,
+        }
+    }
+
+    fn contains(&self, id: StateId) -> bool {
+        let i = self.sparse[id];
+        i < self.dense.len() && self.dense[i] == id
+    }
+
+    fn insert(&mut self, id: StateId) {
+        self.sparse[id] = self.dense.len();
+        self.dense.push(id);
+    }
+
+    fn slots(&self, id: StateId) -> &[Option<usize>] {
+        &self.slots[id * self.slots_len..(id + 1) * self.slots_len]
+    }
+
+    fn slots_mut(&mut self, id: StateId) -> &mut [Option<usize>] {
+        &mut self.slots[id * self.slots_len..(id + 1) * self.slots_len]
+    }
+}
+
+impl<'p> PikeVm<'p> {
+    /// Prepares a search recording the first `slots_len` capture slots.
+    pub fn new(program: &'p Program, slots_len: usize) -> Self {
+        let states = program.insts.len();
+        Self {
+            program,
+            clist: Threads::new(states, slots_len),
+            nlist: Threads::new(states, slots_len),
+            stack: vec![],
+        }
+    }
+
+    /// Finds the leftmost-first match starting at `start` or after, filling
+    /// `slots` with the bounds of the groups. With `char_starts`, matches
+    /// may only start on char boundaries.
+    pub fn search(
+        &mut self,
+        haystack: &[u8],
+        start: usize,
+        char_starts: bool,
+        slots: &mut [Option<usize>],
+    ) -> bool {
+        self.run(haystack, start, false, char_starts, slots)
+    }
+
+    /// Finds the leftmost-first match starting at `start`, which lets the
+    /// search stop as soon as every thread from there has died.
+    pub fn search_anchored(
+        &mut self,
+        haystack: &[u8],
+        start: usize,
+        slots: &mut [Option<usize>],
+    ) -> bool {
+        self.run(haystack, start, true, false, slots)
+    }
+
+    /// Runs every thread over the whole haystack, setting `matched[i]` if
+    /// pattern `i` of a set matches somewhere. Stops early once every
+    /// pattern matched.
+    pub fn which_matches(&mut self, haystack: &[u8], matched: &mut [bool]) {
+        let mut left = matched.iter().filter(|&&m| !m).count();
+        self.clist.dense.clear();
+
+        for pos in 0..=haystack.len() {
+            let (program, stack) = (self.program, &mut self.stack);
+            add_thread(
+                program,
+                stack,
+                &mut self.clist,
+                &mut [],
+                self.program.start,
+                haystack,
+                pos,
+            );
+
+            self.nlist.dense.clear();
+            for i in 0..self.clist.dense.len() {
+                let id = self.clist.dense[i];
+                let next = match &self.program.insts[id] {
+                    Inst::Char { byte, next } => (haystack.get(pos) == Some(byte)).then_some(*next),
+                    Inst::Class { ranges } => haystack.get(pos).and_then(|&b| {
+                        ranges
+                            .iter()
+                            .find(|&&(start, end, _)| start <= b && b <= end)
+                            .map(|&(_, _, next)| next)
+                    }),
+                    Inst::Match => {
+                        let pattern = self.program.pattern_of(id).unwrap();
+                        if !std::mem::replace(&mut matched[pattern], true) {
+                            left -= 1;
+                        }
+                        if left == 0 {
+                            return;
+                        }
+                        None
+                    }
+                    Inst::Split { .. }
+                    | Inst::Jump(_)
+                    | Inst::Save { .. }
+                    | Inst::Assert { .. } => None,
+                };
+
+                if let Some(next) = next {
+                    let (program, stack) = (self.program, &mut self.stack);
+                    add_thread(
+                        program,
+                        stack,
+                        &mut self.nlist,
+                        &mut [],
+                        next,
+                        haystack,
+                        pos + 1,
+                    );
+                }
+            }
+
+            std::mem::swap(&mut self.clist, &mut self.nlist);
+        }
+    }
+
+    fn run(
+        &mut self,
+        haystack: &[u8],
+        start: usize,
+        anchored: bool,
+        char_starts: bool,
+        slots: &mut [Option<usize>],
+    ) -> bool {
+        let mut matched = false;
+        let mut scratch = vec![None; slots.len()];
+        self.clist.dense.clear();
+
+        for pos in start..=haystack.len() {
+            // New threads start with the lowest priority, and only until a
+            // match is found
+            let is_start = !char_starts || haystack.get(pos).is_none_or(|&b| b & 0xC0 != 0x80);
+            if !matched && is_start && (!anchored || pos == start) {
+                scratch.fill(None);
+                let (program, stack) = (self.program, &mut self.stack);
+                add_thread(
+                    program,
+                    stack,
+                    &mut self.clist,
+                    &mut scratch,
+                    self.program.start,
+                    haystack,
+                    pos,
+                );
+            }
+            if self.clist.dense.is_empty() {
+                if matched || anchored {
+                    break;
+                }
+                continue;
+            }
+
+            self.nlist.dense.clear();
+            for i in 0..self.clist.dense.len() {
+                let id = self.clist.dense[i];
+                let next = match &self.program.insts[id] {
+                    Inst::Char { byte, next } => (haystack.get(pos) == Some(byte)).then_some(*next),
+                    Inst::Class { ranges } => haystack.get(pos).and_then(|&b| {
+                        ranges
+                            .iter()
+                            .find(|&&(start, end, _)| start <= b && b <= end)
+                            .map(|&(_, _, next)| next)
+                    }),
+                    Inst::Match => {
+                        slots.copy_from_slice(self.clist.slots(id));
+                        matched = true;
+                        break;
+                    }
+                    Inst::Split { .. }
+                    | Inst::Jump(_)
+                    | Inst::Save { .. }
+                    | Inst::Assert { .. } => None,
+                };
+
+                if let Some(next) = next {
+                    scratch.copy_from_slice(self.clist.slots(id));
+                    let (program, stack) = (self.program, &mut self.stack);
+                    add_thread(
+                        program,
+                        stack,
+                        &mut self.nlist,
+                        &mut scratch,
+                        next,
+                        haystack,
+                        pos + 1,
+                    );
+                }
+            }
+
+            std::mem::swap(&mut self.clist, &mut self.nlist);
+        }
+
+        matched
+    }
+}
+
+/// Adds the thread at `id` and every state reachable from it without
+/// consuming input, in priority order, each with the slots it would have.
+fn add_thread(
+    program: &Program,
+    stack: &mut Vec<Frame>,
+    threads: &mut Threads,
+    slots: &mut [Option<usize>],
+    id: StateId,
+    haystack: &[u8],
+    pos: usize,
+) {
+    stack.push(Frame::Explore(id));
+
+    while let Some(frame) = stack.pop() {
+        let mut id = match frame {
+            Frame::Explore(id) => id,
+            Frame::RestoreSlot(slot, value) => {
+                slots[slot] = value;
+                continue;
+            }
+        };
+
+        // Follow the first branch of each state directly, leaving the
+        // others on the stack
+        while !threads.contains(id) {
+            threads.insert(id);
+            id = match program.insts[id] {
+                Inst::Split { first, second } => {
+                    stack.push(Frame::Explore(second));
+                    first
+                }
+                Inst::Jump(next) => next,
+                Inst::Save { slot, next } => {
+                    if slot < slots.len() {
+                        stack.push(Frame::RestoreSlot(slot, slots[slot]));
+                        slots[slot] = Some(pos);
+                    }
+                    next
+                }
+                Inst::Assert { look, next } => {
+                    let holds = match look {
+                        Look::StartText => pos == 0,
+                        Look::EndText => pos == haystack.len(),
+                        Look::StartLine => pos == 0 || haystack[pos - 1] == b'\n',
+                        Look::EndLine => haystack.get(pos).is_none_or(|&b| b == b'\n'),
+                    };
+                    if !holds {
+                        break;
+                    }
+                    next
+                }
+                Inst::Char { .. } | Inst::Class { .. } | Inst::Match => {
+                    threads.slots_mut(id).copy_from_slice(slots);
+                    break;
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::tokenizer::tokenize;
+
+    fn search(pattern: &str, haystack: &str) -> Option<Vec<Option<usize>>> {
+        let program = Program::compile(
+            &parse(tokenize(pattern.into()).unwrap()).unwrap(),
+            crate::nfa::DEFAULT_SIZE_LIMIT,
+        )
+        .unwrap();
+        let mut slots = vec![None; program.slots_len];
+        let found =
+            PikeVm::new(&program, slots.len()).search(haystack.as_bytes(), 0, true, &mut slots);
+        found.then_some(slots)
+    }
+
+    #[test]
+    fn test_leftmost_first() {
+        assert_eq!(search("a|ab", "xab"), Some(vec![Some(1), Some(2)]));
+        assert_eq!(search("ab|a", "xab"), Some(vec![Some(1), Some(3)]));
+        assert_eq!(search("a+?", "aaa"), Some(vec![Some(0), Some(1)]));
+        assert_eq!(search("b|$", "ab"), Some(vec![Some(1), Some(2)]));
+        assert_eq!(search("x", "ab"), None);
+    }
+
+    #[test]
+    fn test_captures() {
+        assert_eq!(
+            search("(a*)(b)?c", "aac"),
+            Some(vec![Some(0), Some(3), Some(0), Some(2), None, None])
+        );
+        assert_eq!(
+            search("(?:(a)|b)+", "ab"),
+            Some(vec![Some(0), Some(2), Some(0), Some(1)])
+        );
+    }
+
+    #[test]
+    fn test_starts_on_char_boundaries() {
+        assert_eq!(search("", "é"), Some(vec![Some(0), Some(0)]));
+        assert_eq!(search("$", "é"), Some(vec![Some(2), Some(2)]));
+    }
+
+    #[test]
+    fn test_anchored() {
+        let program = Program::compile(
+            &parse(tokenize("(a)b|b".into()).unwrap()).unwrap(),
+            crate::nfa::DEFAULT_SIZE_LIMIT,
+        )
+        .unwrap();
+        let mut slots = vec![None; program.slots_len];
+        let mut vm = PikeVm::new(&program, slots.len());
+
+        assert!(vm.search_anchored(b"xabab", 1, &mut slots));
+        assert_eq!(slots, [Some(1), Some(3), Some(1), Some(2)]);
+        assert!(vm.search_anchored(b"xabab", 2, &mut slots));
+        assert_eq!(slots, [Some(2), Some(3), None, None]);
+        assert!(!vm.search_anchored(b"xabab", 0, &mut slots));
+    }
+
+    #[test]
+    fn test_linear_time() {
+        let haystack = "a".repeat(5000);
+        assert_eq!(search("(a*)*b", &haystack), None);
+        assert_eq!(search("(?:a|a)*(?:a|a)*c", &haystack), None);
+    }
+}
@@ -1,167 +1,535 @@
-use crate::tokenizer::Char;
+use crate::builder::RegexBuilder;
+use crate::lazy_dfa::{Cache, Direction, GaveUp, LazyDfa};
+use crate::nfa;
+use crate::pikevm::PikeVm;
+use crate::strategy::{EngineInfo, Strategy};
+use crate::template::Replacer;
 
-use super::{errors, parser, tokenizer};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, TryLockError};
 
-#[derive(Default, Debug)]
-pub struct Match {
-    is_match: bool,
-    matched: Option<String>,
+use super::{errors, parser};
+
+/// A match in a haystack, borrowing the matched text from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match<'h> {
+    haystack: &'h str,
     start: usize,
     end: usize,
 }
 
-impl Match {
-    pub fn range(&self) -> (usize, usize) {
-        (self.start, self.end)
+impl<'h> Match<'h> {
+    /// Byte offset of the start of the match in the haystack.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Byte offset of the end of the match in the haystack.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Byte offsets of the match in the haystack. Both always fall on char
+    /// boundaries, so they can be used to slice it.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    pub fn as_str(&self) -> &'h str {
+        &self.haystack[self.start..self.end]
+    }
+
+    /// Length of the match in bytes.
+    pub fn len(&self) -> usize {
+        self.end - self.start
     }
 
-    pub fn matched(&self) -> Option<&str> {
-        self.matched.as_deref()
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
     }
 
-    pub fn is_match(&self) -> bool {
-        self.is_match
+    pub(crate) fn new(haystack: &'h str, start: usize, end: usize) -> Self {
+        Self {
+            haystack,
+            start,
+            end,
+        }
     }
 }
 
+/// The groups matched by [`Regex::captures`]. Group 0 is the whole match,
+/// the other groups are numbered by the position of their opening paren.
 #[derive(Debug)]
-pub struct Regex {
-    ast: parser::AstNode,
+pub struct Captures<'h> {
+    haystack: &'h str,
+    slots: Vec<Option<usize>>,
+    names: Arc<[Option<String>]>,
 }
 
-impl Regex {
-    pub fn new(pattern: impl Into<String>) -> Result<Self, errors::ParseError> {
-        let tokens = tokenizer::tokenize(pattern.into())?;
-        let ast = parser::parse(tokens)?;
-        Ok(Self { ast })
+impl<'h> Captures<'h> {
+    /// Returns the match of group `i`, or `None` if the group did not
+    /// participate in the match or does not exist.
+    pub fn get(&self, i: usize) -> Option<Match<'h>> {
+        match (self.slots.get(2 * i)?, self.slots.get(2 * i + 1)?) {
+            (Some(start), Some(end)) => Some(Match::new(self.haystack, *start, *end)),
+            _ => None,
+        }
+    }
+
+    /// Returns the match of the group called `name`, or `None` if there is
+    /// no such group or it did not participate in the match.
+    pub fn name(&self, name: &str) -> Option<Match<'h>> {
+        let index = self.names.iter().position(|n| n.as_deref() == Some(name))?;
+        self.get(index)
+    }
+
+    /// Number of groups in the pattern, including group 0.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+
+    /// Iterates over every group in order, yielding `None` for the groups
+    /// that did not participate in the match.
+    pub fn iter(&self) -> impl Iterator<Item = Option<Match<'h>>> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    fn bounds(&self) -> (usize, usize) {
+        (self.slots[0].unwrap(), self.slots[1].unwrap())
     }
 }
 
-impl Regex {
-    pub fn find(&self, s: impl AsRef<str>) -> Match {
-        let s = s.as_ref();
-        let mut match_res = Match::default();
-
-        match &self.ast {
-            parser::AstNode::StartAnchor(ast) => {
-                if let Some(match_len) = self.match_node(ast, s) {
-                    match_res.is_match = true;
-                    match_res.start = 0;
-                    match_res.end = match_len;
-                    match_res.matched = Some(s[..match_len].into());
-                }
-            }
-            _ => {
-                for (i, _) in s.char_indices() {
-                    if let Some(match_len) = self.match_node(&self.ast, &s[i..]) {
-                        match_res.is_match = true;
-                        match_res.start = i;
-                        match_res.end = i + match_len;
-                        match_res.matched = Some(s[i..i + match_len].into());
-                        break;
-                    }
-                }
-            }
-        }
+/// Iterator over the non-overlapping matches of a [`Regex`] in a haystack,
+/// created by [`Regex::find_iter`].
+#[derive(Debug)]
+pub struct Matches<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    cursor: Cursor,
+}
+
+impl<'h> Iterator for Matches<'_, 'h> {
+    type Item = Match<'h>;
 
-        match_res
+    fn next(&mut self) -> Option<Match<'h>> {
+        let (regex, haystack) = (self.regex, self.haystack);
+        let (start, end) = self
+            .cursor
+            .advance(haystack, |pos| regex.find_at(haystack, pos).map(|b| (b, b)))?;
+        Some(Match::new(haystack, start, end))
     }
+}
 
-    fn match_node(&self, node: &parser::AstNode, s: &str) -> Option<usize> {
-        match node {
-            parser::AstNode::Char(Char::Escape(_)) => todo!(),
-            parser::AstNode::Char(tokenizer::Char::Lit(c)) => {
-                if s.starts_with(*c) {
-                    Some(1)
-                } else {
-                    None
-                }
-            }
-            parser::AstNode::Char(tokenizer::Char::Dot) => {
-                if !s.is_empty() {
-                    Some(1)
-                } else {
-                    None
-                }
-            }
-            parser::AstNode::CharClass(chars) => {
-                if !s.is_empty() && chars.contains(&tokenizer::Char::Lit(s.chars().next().unwrap()))
-                {
-                    Some(1)
+/// Iterator over the pieces of a haystack between the matches of a
+/// [`Regex`], created by [`Regex::split`] and its siblings.
+#[derive(Debug)]
+pub struct Split<'r, 'h> {
+    regex: &'r Regex,
+    haystack: &'h str,
+    cursor: Cursor,
+    /// Where the next piece starts, or `None` once the last one was yielded
+    next_start: Option<usize>,
+    /// Number of pieces left to yield, the last one being the rest of the
+    /// haystack
+    limit: usize,
+    /// Whether pieces end with the match that follows them
+    inclusive: bool,
+}
+
+impl<'h> Iterator for Split<'_, 'h> {
+    type Item = &'h str;
+
+    fn next(&mut self) -> Option<&'h str> {
+        let (regex, haystack) = (self.regex, self.haystack);
+        let start = self.next_start?;
+        let found = match self.limit {
+            1 => None,
+            _ => self
+                .cursor
+                .advance(haystack, |pos| regex.find_at(haystack, pos).map(|b| (b, b))),
+        };
+
+        match found {
+            Some((match_start, match_end)) => {
+                self.next_start = Some(match_end);
+                self.limit -= 1;
+                let end = if self.inclusive {
+                    match_end
                 } else {
-                    None
-                }
+                    match_start
+                };
+                Some(&haystack[start..end])
             }
-            parser::AstNode::Chain(nodes) => self.match_chain(nodes, s),
-            parser::AstNode::Quantifier(q, n) => self.match_quantifier(*q, n, s),
-            parser::AstNode::EndAnchor => {
-                if s.is_empty() {
-                    Some(0)
-                } else {
-                    None
+            None => {
+                self.next_start = None;
+                match self.inclusive && start == haystack.len() {
+                    true => None,
+                    false => Some(&haystack[start..]),
                 }
             }
-            parser::AstNode::StartAnchor(_) => unreachable!(),
-            parser::AstNode::CaptureGroup(_) => todo!(),
         }
     }
+}
 
-    fn match_chain(&self, nodes: &[parser::AstNode], s: &str) -> Option<usize> {
-        let mut current_str = s;
-        let mut total_matched = 0;
+/// Where the next search of an iteration over successive non-overlapping
+/// matches starts.
+#[derive(Debug, Default)]
+struct Cursor {
+    pos: usize,
+    last_end: Option<usize>,
+}
 
-        for node in nodes.iter() {
-            match self.match_node(node, current_str) {
-                Some(match_len) => {
-                    total_matched += match_len;
-                    current_str = &current_str[match_len..];
-                }
-                None => return None,
+impl Cursor {
+    /// Runs `search` from the cursor, which returns a match with its bounds,
+    /// and moves past the match. An empty match right where the previous one
+    /// ended is skipped, searching again one char further.
+    fn advance<T>(
+        &mut self,
+        haystack: &str,
+        mut search: impl FnMut(usize) -> Option<(T, (usize, usize))>,
+    ) -> Option<T> {
+        while self.pos <= haystack.len() {
+            let (found, (start, end)) = search(self.pos)?;
+            if start == end && Some(end) == self.last_end {
+                let skipped = haystack[end..].chars().next();
+                self.pos = end + skipped.map_or(1, char::len_utf8);
+                continue;
             }
+
+            self.pos = end;
+            self.last_end = Some(end);
+            return Some(found);
         }
+        None
+    }
+}
 
-        Some(total_matched)
+#[derive(Debug)]
+pub struct Regex {
+    pub(crate) program: nfa::Program,
+    pub(crate) reverse: nfa::Program,
+    strategy: Strategy,
+    dfa_caches: Mutex<DfaCaches>,
+    dfa_cache_capacity: usize,
+    pub(crate) capture_names: Arc<[Option<String>]>,
+}
+
+/// Caches of the lazy DFAs, which share the memory budget of a regex.
+#[derive(Debug)]
+struct DfaCaches {
+    forward: Cache,
+    reverse: Cache,
+}
+
+impl DfaCaches {
+    fn new(capacity: usize) -> Self {
+        Self {
+            forward: Cache::new(capacity / 2),
+            reverse: Cache::new(capacity / 2),
+        }
     }
+}
 
-    fn match_quantifier(
-        &self,
-        quantifier: tokenizer::Quantifier,
-        node: &parser::AstNode,
-        s: &str,
-    ) -> Option<usize> {
-        match quantifier {
-            tokenizer::Quantifier::Any => self.match_any(node, s),
-            tokenizer::Quantifier::Many => self.match_many(node, s),
-            tokenizer::Quantifier::Maybe => self.match_option(node, s),
+impl Regex {
+    /// Compiles `pattern` with the default options, which a
+    /// [`RegexBuilder`](crate::RegexBuilder) can change.
+    ///
+    /// `.` matches any char but `\n`, unless the `s` flag is set with `(?s)`
+    /// or [`RegexBuilder::dot_matches_new_line`](crate::RegexBuilder::dot_matches_new_line).
+    pub fn new(pattern: impl Into<String>) -> Result<Self, errors::ParseError> {
+        RegexBuilder::new(pattern).build()
+    }
+
+    /// Compiles `ast`, failing if either of its programs takes more than
+    /// `size_limit` bytes.
+    pub(crate) fn from_ast(
+        ast: parser::AstNode,
+        size_limit: usize,
+        dfa_cache_capacity: usize,
+    ) -> Result<Self, errors::ParseError> {
+        let program = nfa::Program::compile(&ast, size_limit)?;
+        Ok(Self {
+            strategy: Strategy::new(&ast, &program, dfa_cache_capacity),
+            reverse: nfa::Program::compile_reverse(&ast, size_limit)?,
+            program,
+            dfa_caches: Mutex::new(DfaCaches::new(dfa_cache_capacity)),
+            dfa_cache_capacity,
+            capture_names: ast.capture_names().into(),
+        })
+    }
+
+    /// Names of the groups indexed by group number, starting with group 0.
+    /// Unnamed groups, including group 0, yield `None`.
+    pub fn capture_names(&self) -> impl Iterator<Item = Option<&str>> + '_ {
+        self.capture_names.iter().map(Option::as_deref)
+    }
+
+    /// Tells which search path was picked for the pattern, to help debug
+    /// performance.
+    pub fn engine_info(&self) -> EngineInfo {
+        self.strategy.info()
+    }
+}
+
+impl Regex {
+    /// Returns whether the pattern matches anywhere in `s`, which is faster
+    /// than finding where.
+    pub fn is_match(&self, s: impl AsRef<str>) -> bool {
+        self.is_match_bytes(s.as_ref().as_bytes())
+    }
+
+    /// Returns the leftmost-first match in `s`, or `None` if the pattern
+    /// does not match.
+    pub fn find<'h>(&self, s: &'h str) -> Option<Match<'h>> {
+        let (start, end) = self.find_at(s, 0)?;
+        Some(Match::new(s, start, end))
+    }
+
+    /// Iterates over the successive non-overlapping matches in `s`. After an
+    /// empty match, the search moves on by one char, and an empty match
+    /// right where the previous match ended is skipped.
+    pub fn find_iter<'r, 'h>(&'r self, s: &'h str) -> Matches<'r, 'h> {
+        Matches {
+            regex: self,
+            haystack: s,
+            cursor: Cursor::default(),
+        }
+    }
+
+    /// Iterates over the pieces of `s` separated by matches, like
+    /// [`str::split`]: matches at either end yield empty pieces, and so do
+    /// adjacent matches.
+    pub fn split<'r, 'h>(&'r self, s: &'h str) -> Split<'r, 'h> {
+        self.splitn(s, usize::MAX)
+    }
+
+    /// Like [`Regex::split`], but yields at most `limit` pieces, the last
+    /// one being the rest of `s`.
+    pub fn splitn<'r, 'h>(&'r self, s: &'h str, limit: usize) -> Split<'r, 'h> {
+        Split {
+            regex: self,
+            haystack: s,
+            cursor: Cursor::default(),
+            next_start: (limit > 0).then_some(0),
+            limit,
+            inclusive: false,
+        }
+    }
+
+    /// Like [`Regex::split`], but each piece ends with the match that follows
+    /// it, like [`str::split_inclusive`]. The piece after the last match is
+    /// only yielded if not empty.
+    pub fn split_inclusive<'r, 'h>(&'r self, s: &'h str) -> Split<'r, 'h> {
+        Split {
+            inclusive: true,
+            ..self.split(s)
+        }
+    }
+
+    /// Returns the groups of the leftmost-first match, or `None` if the
+    /// pattern does not match.
+    pub fn captures<'h>(&self, s: &'h str) -> Option<Captures<'h>> {
+        self.captures_at(s, 0, self.program.slots_len)
+    }
+
+    /// Replaces the leftmost-first match in `s` with what `rep` computes
+    /// for it, borrowing `s` back when nothing matched. Strings are expanded
+    /// as a [`Template`](crate::Template).
+    pub fn replace<'h>(&self, s: &'h str, rep: impl Replacer) -> Cow<'h, str> {
+        self.replacen(s, 1, rep)
+    }
+
+    /// Replaces every non-overlapping match in `s`, as found by
+    /// [`Regex::find_iter`], with what `rep` computes for it.
+    pub fn replace_all<'h>(&self, s: &'h str, rep: impl Replacer) -> Cow<'h, str> {
+        self.replacen(s, 0, rep)
+    }
+
+    /// Replaces the first `limit` non-overlapping matches in `s`, or all of
+    /// them if `limit` is 0, with what `rep` computes for them.
+    pub fn replacen<'h>(&self, s: &'h str, limit: usize, mut rep: impl Replacer) -> Cow<'h, str> {
+        // Group 0 alone comes from the lazy DFAs without the PikeVM
+        let slots_len = match rep.needs_groups() {
+            true => self.program.slots_len,
+            false => 2,
+        };
+        let search = |pos| {
+            let caps = self.captures_at(s, pos, slots_len)?;
+            let bounds = caps.bounds();
+            Some((caps, bounds))
+        };
+        let limit = if limit == 0 { usize::MAX } else { limit };
+        let mut cursor = Cursor::default();
+        let mut replaced = String::new();
+        let mut last_end = 0;
+
+        for _ in 0..limit {
+            let Some(caps) = cursor.advance(s, search) else {
+                break;
+            };
+            let (start, end) = caps.bounds();
+            replaced.push_str(&s[last_end..start]);
+            rep.replace_append(&caps, &mut replaced);
+            last_end = end;
         }
+
+        if cursor.last_end.is_none() {
+            return Cow::Borrowed(s);
+        }
+        replaced.push_str(&s[last_end..]);
+        Cow::Owned(replaced)
     }
 
-    fn match_any(&self, node: &parser::AstNode, s: &str) -> Option<usize> {
-        // Zero or more
-        let mut current_str = s;
-        let mut count = 0_usize;
+    /// Returns the groups of the leftmost-first match starting at `start` or
+    /// after, resolving only as many as `slots_len` slots hold.
+    fn captures_at<'h>(&self, s: &'h str, start: usize, slots_len: usize) -> Option<Captures<'h>> {
+        let mut slots = vec![None; slots_len];
+
+        match self.search_bytes(s.as_bytes(), start, true, &mut slots) {
+            true => Some(Captures {
+                haystack: s,
+                slots,
+                names: self.capture_names.clone(),
+            }),
+            false => None,
+        }
+    }
+
+    /// Finds the bounds of the leftmost-first match starting at `start` or
+    /// after.
+    fn find_at(&self, s: &str, start: usize) -> Option<(usize, usize)> {
+        let mut slots = [None; 2];
+        match self.search_bytes(s.as_bytes(), start, true, &mut slots) {
+            true => Some((slots[0].unwrap(), slots[1].unwrap())),
+            false => None,
+        }
+    }
+
+    pub(crate) fn is_match_bytes(&self, s: &[u8]) -> bool {
+        match &self.strategy {
+            Strategy::Literal(finder) => return finder.find(s).is_some(),
+            Strategy::Literals(literals) => return literals.find(s).is_some(),
+            _ => {}
+        }
+        let Some(start) = self.skip(s) else {
+            return false;
+        };
+
+        let found = match &self.strategy {
+            Strategy::Anchored | Strategy::LazyDfa(_) => self.with_dfa_caches(|caches| {
+                LazyDfa::new(&self.program, Direction::Forward)
+                    .anchored(matches!(self.strategy, Strategy::Anchored))
+                    .find_end(&mut caches.forward, s, start, true)
+            }),
+            Strategy::Literal(_) | Strategy::Literals(_) | Strategy::PikeVm(_) => Err(GaveUp),
+        };
 
-        while let Some(n) = self.match_node(node, current_str) {
-            count += n;
-            current_str = &current_str[n..];
+        match found {
+            Ok(found) => found.is_some(),
+            Err(GaveUp) => PikeVm::new(&self.program, 0).search(s, start, false, &mut []),
         }
+    }
+
+    /// Finds the leftmost-first match starting at `start` or after,
+    /// recording the bounds of each group in `slots` as `[start0, end0,
+    /// start1, end1, ...]`. Groups beyond `slots.len() / 2` are matched but
+    /// not recorded. With `char_starts`, matches may only start on char
+    /// boundaries. Anchors still see the whole haystack.
+    ///
+    /// The strategy finds the bounds of the match, or tells there is none,
+    /// so the PikeVM only runs over the match when groups are needed, or
+    /// over the whole haystack when the lazy DFAs give up.
+    pub(crate) fn search_bytes(
+        &self,
+        s: &[u8],
+        start: usize,
+        char_starts: bool,
+        slots: &mut [Option<usize>],
+    ) -> bool {
+        let Some(from) = self.skip(&s[start..]).map(|skipped| start + skipped) else {
+            return false;
+        };
+
+        let bounds = match &self.strategy {
+            Strategy::Literal(finder) => {
+                let len = finder.needle().len();
+                Ok(finder
+                    .find(&s[start..])
+                    .map(|found| (start + found, start + found + len)))
+            }
+            Strategy::Literals(literals) => Ok(literals.find(&s[start..]).map(|found| {
+                let (found_start, found_end) = found.range();
+                (start + found_start, start + found_end)
+            })),
+            // Matches can only start at 0
+            Strategy::Anchored if start > 0 => Ok(None),
+            Strategy::Anchored => self
+                .with_dfa_caches(|caches| {
+                    LazyDfa::new(&self.program, Direction::Forward)
+                        .anchored(true)
+                        .find_end(&mut caches.forward, s, 0, false)
+                })
+                .map(|end| end.map(|end| (0, end))),
+            Strategy::LazyDfa(_) => {
+                self.with_dfa_caches(|caches| self.dfa_find(caches, s, start, from))
+            }
+            Strategy::PikeVm(_) => Err(GaveUp),
+        };
 
-        Some(count)
+        match bounds {
+            Ok(None) => false,
+            Ok(Some((start, end))) if slots.len() <= 2 => {
+                slots.copy_from_slice(&[Some(start), Some(end)][..slots.len()]);
+                true
+            }
+            Ok(Some((start, _))) => {
+                PikeVm::new(&self.program, slots.len()).search_anchored(s, start, slots)
+            }
+            Err(GaveUp) => {
+                PikeVm::new(&self.program, slots.len()).search(s, from, char_starts, slots)
+            }
+        }
     }
 
-    fn match_many(&self, node: &parser::AstNode, s: &str) -> Option<usize> {
-        // One or more
-        match self.match_node(node, s) {
-            Some(first_len) => {
-                let rest_len = self.match_any(node, &s[first_len..]).unwrap_or(0);
-                Some(first_len + rest_len)
+    /// Runs the prefilter of the strategy, returning where a match may start
+    /// at the earliest, or `None` if there is none.
+    fn skip(&self, s: &[u8]) -> Option<usize> {
+        match &self.strategy {
+            Strategy::LazyDfa(Some(prefilter)) | Strategy::PikeVm(Some(prefilter)) => {
+                prefilter.skip(s)
             }
-            None => None,
+            _ => Some(0),
         }
     }
 
-    fn match_option(&self, node: &parser::AstNode, s: &str) -> Option<usize> {
-        // Zero or one
-        self.match_node(node, s).or(Some(0))
+    /// Finds the bounds of the leftmost-first match starting at `start` or
+    /// after, where `from` is the earliest position one may start at.
+    fn dfa_find(
+        &self,
+        caches: &mut DfaCaches,
+        s: &[u8],
+        start: usize,
+        from: usize,
+    ) -> Result<Option<(usize, usize)>, GaveUp> {
+        let forward = LazyDfa::new(&self.program, Direction::Forward);
+        let Some(end) = forward.find_end(&mut caches.forward, s, from, false)? else {
+            return Ok(None);
+        };
+
+        let reverse = LazyDfa::new(&self.reverse, Direction::Reverse);
+        let start = reverse.find_start(&mut caches.reverse, s, start, end)?;
+        Ok(Some((start.expect("a match ends here"), end)))
+    }
+
+    fn with_dfa_caches<T>(&self, f: impl FnOnce(&mut DfaCaches) -> T) -> T {
+        match self.dfa_caches.try_lock() {
+            Ok(mut caches) => f(&mut caches),
+            Err(TryLockError::Poisoned(poisoned)) => f(&mut poisoned.into_inner()),
+            // Another thread is searching, so build states from scratch
+            // rather than wait for it
+            Err(TryLockError::WouldBlock) => f(&mut DfaCaches::new(self.dfa_cache_capacity)),
+        }
     }
 }
@@ -0,0 +1,219 @@
+use crate::errors::SetError;
+use crate::lazy_dfa::{self, Cache, Direction, GaveUp, LazyDfa};
+use crate::nfa::{self, Program};
+use crate::pikevm::PikeVm;
+use crate::{parser, tokenizer};
+
+use std::sync::{Mutex, TryLockError};
+
+/// Many patterns compiled into one automaton, telling which of them match
+/// a haystack in a single pass over it.
+#[derive(Debug)]
+pub struct RegexSet {
+    program: Program,
+    dfa_cache: Mutex<Cache>,
+}
+
+impl RegexSet {
+    /// Compiles `patterns`, failing with the index of the first one that
+    /// does not parse, or that takes the combined program over the default
+    /// size limit of `Regex`.
+    pub fn new<I, P>(patterns: I) -> Result<Self, SetError>
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<String>,
+    {
+        let asts = patterns
+            .into_iter()
+            .enumerate()
+            .map(|(index, pattern)| {
+                tokenizer::tokenize(pattern.into())
+                    .and_then(parser::parse)
+                    .map_err(|err| SetError::BadPattern(index, err))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            program: Program::compile_set(&asts, nfa::DEFAULT_SIZE_LIMIT)?,
+            dfa_cache: Mutex::new(Cache::new(lazy_dfa::DEFAULT_CACHE_CAPACITY)),
+        })
+    }
+
+    /// Number of patterns in the set.
+    pub fn len(&self) -> usize {
+        self.program.matches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether any pattern matches anywhere in `s`, which is faster
+    /// than finding which.
+    pub fn is_match(&self, s: impl AsRef<str>) -> bool {
+        let s = s.as_ref().as_bytes();
+        let found = self.with_dfa_cache(|cache| self.dfa().find_end(cache, s, 0, true));
+
+        match found {
+            Ok(found) => found.is_some(),
+            Err(GaveUp) => {
+                let mut matched = vec![false; self.len()];
+                PikeVm::new(&self.program, 0).which_matches(s, &mut matched);
+                matched.contains(&true)
+            }
+        }
+    }
+
+    /// Returns which patterns match somewhere in `s`.
+    pub fn matches(&self, s: impl AsRef<str>) -> SetMatches {
+        let s = s.as_ref().as_bytes();
+        let mut matched = vec![false; self.len()];
+
+        let found = self.with_dfa_cache(|cache| self.dfa().which_matches(cache, s, &mut matched));
+        if found == Err(GaveUp) {
+            PikeVm::new(&self.program, 0).which_matches(s, &mut matched);
+        }
+        SetMatches { matched }
+    }
+
+    fn dfa(&self) -> LazyDfa<'_> {
+        LazyDfa::new(&self.program, Direction::Forward).all_matches(true)
+    }
+
+    fn with_dfa_cache<T>(&self, f: impl FnOnce(&mut Cache) -> T) -> T {
+        match self.dfa_cache.try_lock() {
+            Ok(mut cache) => f(&mut cache),
+            Err(TryLockError::Poisoned(poisoned)) => f(&mut poisoned.into_inner()),
+            // Another thread is searching, so build states from scratch
+            // rather than wait for it
+            Err(TryLockError::WouldBlock) => f(&mut Cache::new(lazy_dfa::DEFAULT_CACHE_CAPACITY)),
+        }
+    }
+}
+
+/// The patterns of a [`RegexSet`] that matched a haystack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetMatches {
+    matched: Vec<bool>,
+}
+
+impl SetMatches {
+    /// Whether any pattern matched.
+    pub fn matched_any(&self) -> bool {
+        self.matched.contains(&true)
+    }
+
+    /// Whether the pattern at `index` matched.
+    pub fn matched(&self, index: usize) -> bool {
+        self.matched[index]
+    }
+
+    /// Number of patterns in the set, matched or not.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.matched.len()
+    }
+
+    /// Iterates over the indices of the patterns that matched, in order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.matched
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &matched)| matched.then_some(index))
+    }
+}
+
+impl<'a> IntoIterator for &'a SetMatches {
+    type Item = usize;
+    type IntoIter = Box<dyn Iterator<Item = usize> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        Box::new(self.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::ParseError;
+
+    fn matches(set: &RegexSet, haystack: &str) -> Vec<usize> {
+        set.matches(haystack).iter().collect()
+    }
+
+    #[test]
+    fn test_matches() {
+        let set = RegexSet::new([r"\d+", "^foo", "bar$", "a|é", ""]).unwrap();
+
+        assert_eq!(matches(&set, "foo 42"), [0, 1, 4]);
+        assert_eq!(matches(&set, "a foobar"), [2, 3, 4]);
+        assert_eq!(matches(&set, "café"), [3, 4]);
+        assert!(set.is_match("xyz"));
+
+        let set = RegexSet::new(["x$", "^$"]).unwrap();
+        assert_eq!(matches(&set, ""), [1]);
+        assert_eq!(matches(&set, "ax"), [0]);
+        assert!(!set.matches("xa").matched_any());
+        assert!(!set.is_match("xa"));
+    }
+
+    #[test]
+    fn test_agrees_with_regex() {
+        let patterns = ["a+b", "(?:ab)*c$", "b|c", "^a", r"\w{3}"];
+        let set = RegexSet::new(patterns).unwrap();
+        let regexes = patterns.map(|pattern| crate::Regex::new(pattern).unwrap());
+
+        for haystack in ["", "ab", "aab", "ababc", "xc", "xa", "ééé"] {
+            let expected: Vec<_> = regexes.iter().map(|r| r.is_match(haystack)).collect();
+            assert_eq!(set.matches(haystack).matched, expected, "{:?}", haystack);
+
+            let mut matched = vec![false; set.len()];
+            PikeVm::new(&set.program, 0).which_matches(haystack.as_bytes(), &mut matched);
+            assert_eq!(matched, expected, "{:?}", haystack);
+        }
+    }
+
+    #[test]
+    fn test_line_anchors_next_to_match() {
+        let set = RegexSet::new(["a", "(?m)a$", "(?m)^b", "b$"]).unwrap();
+        assert_eq!(matches(&set, "a\nb"), [0, 1, 2, 3]);
+        assert_eq!(matches(&set, "ab\nb"), [0, 2, 3]);
+
+        for haystack in ["a\nb", "ab\nb", "ba\n", "a", "\n"] {
+            let mut expected = vec![false; set.len()];
+            PikeVm::new(&set.program, 0).which_matches(haystack.as_bytes(), &mut expected);
+            assert_eq!(set.matches(haystack).matched, expected, "{:?}", haystack);
+        }
+    }
+
+    #[test]
+    fn test_empty_set() {
+        let set = RegexSet::new(Vec::<String>::new()).unwrap();
+        assert!(set.is_empty());
+        assert!(!set.is_match("a"));
+        assert_eq!(set.matches("a").len(), 0);
+    }
+
+    #[test]
+    fn test_bad_pattern() {
+        let err = RegexSet::new(["a", "b(", "c"]).unwrap_err();
+        assert!(matches!(err, SetError::BadPattern(1, _)));
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let huge = "(?:(?:a{1000}){1000}){20}";
+        let err = RegexSet::new(["a", huge, "b"]).unwrap_err();
+        assert!(matches!(
+            err,
+            SetError::BadPattern(1, ParseError::SizeLimitExceeded(nfa::DEFAULT_SIZE_LIMIT))
+        ));
+
+        // No pattern is too large alone, but together they go over the
+        // limit, which the first pattern past it is blamed for
+        let large = r"(?-u)\w{1000}";
+        let err = RegexSet::new([large; 300]).unwrap_err();
+        assert!(matches!(err, SetError::BadPattern(index, _) if index > 0 && index < 300));
+        assert!(crate::Regex::new(large).is_ok());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::lazy_dfa::DfaId;
+use crate::literal::{Finder, MultiLiteral, Prefilter, Prefixes, Required};
+use crate::nfa::{Program, StateId};
+use crate::parser::AstNode;
+
+/// The lazy DFA is skipped unless its cache can hold this many states as
+/// large as the NFA, since it would otherwise keep clearing and give up.
+const MIN_DFA_STATES: usize = 16;
+
+/// How a [`Regex`](crate::Regex) searches, as reported by
+/// [`Regex::engine_info`](crate::Regex::engine_info).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineInfo {
+    /// The pattern is a plain string, found by substring search, or an
+    /// alternation of them, found by an Aho–Corasick automaton
+    Literal,
+    /// Matches can only start at the start of the haystack, so a lazy DFA
+    /// anchored there finds where they end
+    Anchored,
+    /// Lazy DFAs find the bounds of matches, and the PikeVM only runs over
+    /// a match when its groups are needed. Both skip ahead to a literal
+    /// prefix when the pattern has one.
+    LazyDfa,
+    /// The NFA is too large for the lazy DFA cache, so the PikeVM does
+    /// every search
+    PikeVm,
+}
+
+/// The search path picked for a pattern, with the data it needs.
+#[derive(Debug)]
+pub enum Strategy {
+    Literal(Finder),
+    Literals(MultiLiteral),
+    Anchored,
+    LazyDfa(Option<Prefilter>),
+    PikeVm(Option<Prefilter>),
+}
+
+impl Strategy {
+    /// Picks the cheapest path able to search for `ast`. The syntax has no
+    /// backreferences, so there is always one that runs in linear time.
+    pub fn new(ast: &AstNode, program: &Program, dfa_cache_capacity: usize) -> Self {
+        let prefixes = Prefixes::new(ast);
+        if prefixes.exact && prefixes.is_useful() && ast.captures_len() == 0 {
+            return match &prefixes.literals[..] {
+                [literal] => Strategy::Literal(Finder::new(literal)),
+                literals => Strategy::Literals(MultiLiteral::new(literals)),
+            };
+        }
+        let prefilter = Prefilter::new(&Required::new(ast), &prefixes);
+
+        // Each of the two lazy DFAs gets half the capacity
+        let largest_state = std::mem::size_of::<[DfaId; 256]>()
+            + 2 * program.insts.len() * std::mem::size_of::<StateId>();
+        if largest_state * MIN_DFA_STATES > dfa_cache_capacity / 2 {
+            Strategy::PikeVm(prefilter)
+        } else if is_anchored(ast) {
+            Strategy::Anchored
+        } else {
+            Strategy::LazyDfa(prefilter)
+        }
+    }
+
+    pub fn info(&self) -> EngineInfo {
+        match self {
+            Strategy::Literal(_) | Strategy::Literals(_) => EngineInfo::Literal,
+            Strategy::Anchored => EngineInfo::Anchored,
+            Strategy::LazyDfa(_) => EngineInfo::LazyDfa,
+            Strategy::PikeVm(_) => EngineInfo::PikeVm,
+        }
+    }
+}
+
+/// Whether every match of `node` must start at the start of the text.
+fn is_anchored(node: &AstNode) -> bool {
+    match node {
+        AstNode::StartAnchor => true,
+        AstNode::Chain(nodes) => nodes.first().is_some_and(is_anchored),
+        AstNode::Alternation(branches) => branches.iter().all(is_anchored),
+        AstNode::NonCapturingGroup(node) | AstNode::CaptureGroup(_, _, node) => is_anchored(node),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::tokenizer::tokenize;
+
+    fn strategy(pattern: &str, dfa_cache_capacity: usize) -> EngineInfo {
+        let ast = parse(tokenize(pattern.into()).unwrap()).unwrap();
+        let program = Program::compile(&ast, crate::nfa::DEFAULT_SIZE_LIMIT).unwrap();
+        Strategy::new(&ast, &program, dfa_cache_capacity).info()
+    }
+
+    #[test]
+    fn test_strategy() {
+        let capacity = crate::lazy_dfa::DEFAULT_CACHE_CAPACITY;
+
+        assert_eq!(strategy("abc", capacity), EngineInfo::Literal);
+        assert_eq!(strategy("(?:é)x", capacity), EngineInfo::Literal);
+        assert_eq!(strategy("foo|bar|[xy]z", capacity), EngineInfo::Literal);
+        assert_eq!(strategy("(abc)", capacity), EngineInfo::LazyDfa);
+        assert_eq!(strategy("foo|bar|$", capacity), EngineInfo::LazyDfa);
+        assert_eq!(strategy("", capacity), EngineInfo::LazyDfa);
+        assert_eq!(strategy("^ab|^c", capacity), EngineInfo::Anchored);
+        assert_eq!(strategy("^a|b", capacity), EngineInfo::LazyDfa);
+        assert_eq!(strategy("a+b", capacity), EngineInfo::LazyDfa);
+        assert_eq!(strategy("a+b", 0), EngineInfo::PikeVm);
+        assert_eq!(strategy("abc", 0), EngineInfo::Literal);
+    }
+}
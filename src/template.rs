@@ -0,0 +1,258 @@
+use crate::regex::Captures;
+
+/// Computes the replacement of each match for
+/// [`Regex::replace`](crate::Regex::replace) and its siblings.
+///
+/// Strings are expanded as a [`Template`], closures are called with the
+/// groups of each match, and [`NoExpand`] is inserted as is.
+///
+/// ```
+/// use regex_engine::{Captures, NoExpand, Regex};
+///
+/// let regex = Regex::new(r"\d{4}(\d{4})").unwrap();
+/// let masked = regex.replace_all("1234567 12345678", |caps: &Captures| {
+///     format!("****{}", caps.get(1).unwrap().as_str())
+/// });
+/// assert_eq!(masked, "1234567 ****5678");
+///
+/// let regex = Regex::new("price").unwrap();
+/// assert_eq!(regex.replace("price: 5", NoExpand("$5")), "$5: 5");
+/// ```
+pub trait Replacer {
+    /// Appends the replacement for the match of `caps` to `dst`.
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String);
+
+    /// Whether the replacement depends on groups other than the whole
+    /// match, which take a slower search to resolve. When `false`, `caps`
+    /// only holds group 0.
+    fn needs_groups(&self) -> bool {
+        true
+    }
+}
+
+impl Replacer for &str {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        parse(self).for_each(|piece| piece.expand(caps, dst));
+    }
+
+    fn needs_groups(&self) -> bool {
+        parse(self).any(|piece| piece.is_group())
+    }
+}
+
+impl Replacer for String {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        self.as_str().replace_append(caps, dst)
+    }
+
+    fn needs_groups(&self) -> bool {
+        self.as_str().needs_groups()
+    }
+}
+
+impl Replacer for &String {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        self.as_str().replace_append(caps, dst)
+    }
+
+    fn needs_groups(&self) -> bool {
+        self.as_str().needs_groups()
+    }
+}
+
+impl Replacer for &Template {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        self.expand(caps, dst)
+    }
+
+    fn needs_groups(&self) -> bool {
+        self.pieces.iter().any(Piece::is_group)
+    }
+}
+
+impl<F, T> Replacer for F
+where
+    F: FnMut(&Captures) -> T,
+    T: AsRef<str>,
+{
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        dst.push_str(self(caps).as_ref())
+    }
+}
+
+/// A replacement inserted as is, with no `$` expansion, for text that comes
+/// from users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoExpand<'t>(pub &'t str);
+
+impl Replacer for NoExpand<'_> {
+    fn replace_append(&mut self, _caps: &Captures, dst: &mut String) {
+        dst.push_str(self.0)
+    }
+
+    fn needs_groups(&self) -> bool {
+        false
+    }
+}
+
+/// A replacement string parsed once, to be expanded for each match by
+/// [`Regex::replace`](crate::Regex::replace) and its siblings.
+///
+/// `$N` and `${N}` stand for the text of group `N`, `$0` for the whole
+/// match, `${name}` for the group called `name`, and `$$` for a literal `$`.
+/// `$N` takes every digit that follows, so `${1}0` is needed for group 1
+/// followed by a `0`. Groups that did not participate in the match, or do
+/// not exist, expand to nothing. A `$` starting none of these is kept as is.
+///
+/// ```
+/// use regex_engine::{Regex, Template};
+///
+/// let regex = Regex::new(r"(?<last>\w+), (\w+)").unwrap();
+/// let template = Template::new("$2 ${last}");
+/// assert_eq!(regex.replace("Turing, Alan", &template), "Alan Turing");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    pieces: Vec<Piece<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece<S> {
+    Literal(S),
+    Group(usize),
+    Named(S),
+}
+
+impl Template {
+    pub fn new(template: &str) -> Self {
+        let mut pieces: Vec<Piece<String>> = vec![];
+        for piece in parse(template) {
+            match (pieces.last_mut(), piece) {
+                (Some(Piece::Literal(last)), Piece::Literal(literal)) => last.push_str(literal),
+                (_, Piece::Literal(literal)) => pieces.push(Piece::Literal(literal.into())),
+                (_, Piece::Group(index)) => pieces.push(Piece::Group(index)),
+                (_, Piece::Named(name)) => pieces.push(Piece::Named(name.into())),
+            }
+        }
+        Self { pieces }
+    }
+
+    /// Appends the expansion for the match of `caps` to `dst`.
+    pub fn expand(&self, caps: &Captures, dst: &mut String) {
+        self.pieces.iter().for_each(|piece| piece.expand(caps, dst));
+    }
+}
+
+impl From<&str> for Template {
+    fn from(template: &str) -> Self {
+        Self::new(template)
+    }
+}
+
+impl<S: AsRef<str>> Piece<S> {
+    fn expand(&self, caps: &Captures, dst: &mut String) {
+        let text = match self {
+            Piece::Literal(literal) => Some(literal.as_ref()),
+            Piece::Group(index) => caps.get(*index).map(|m| m.as_str()),
+            Piece::Named(name) => caps.name(name.as_ref()).map(|m| m.as_str()),
+        };
+        dst.push_str(text.unwrap_or_default());
+    }
+
+    /// Whether the piece refers to a group other than the whole match.
+    fn is_group(&self) -> bool {
+        !matches!(self, Piece::Literal(_) | Piece::Group(0))
+    }
+}
+
+/// Splits `template` into literal text and group references, as it goes,
+/// so that a string can be expanded without parsing it up front.
+fn parse(template: &str) -> impl Iterator<Item = Piece<&str>> {
+    let mut rest = template;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let (piece, len) = match rest.find('$') {
+            Some(0) => parse_reference(rest).unwrap_or_else(|| {
+                let len = if rest.starts_with("$$") { 2 } else { 1 };
+                (Piece::Literal("$"), len)
+            }),
+            Some(dollar) => (Piece::Literal(&rest[..dollar]), dollar),
+            None => (Piece::Literal(rest), rest.len()),
+        };
+        rest = &rest[len..];
+        Some(piece)
+    })
+}
+
+/// Parses the group reference `s` starts with, right after its `$`, and
+/// returns it with the length it takes up.
+fn parse_reference(s: &str) -> Option<(Piece<&str>, usize)> {
+    let rest = &s[1..];
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    if digits > 0 {
+        let index = rest[..digits].parse().ok()?;
+        return Some((Piece::Group(index), 1 + digits));
+    }
+
+    let name = rest.strip_prefix('{')?.split('}').next()?;
+    if name.len() + 1 == rest.len() || name.is_empty() {
+        // No closing brace, or nothing in between
+        return None;
+    }
+    let piece = match name.parse() {
+        Ok(index) if name.bytes().all(|b| b.is_ascii_digit()) => Piece::Group(index),
+        _ => Piece::Named(name),
+    };
+    Some((piece, name.len() + 3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let literal = |s: &str| Piece::Literal(s.into());
+
+        assert_eq!(Template::new("").pieces, []);
+        assert_eq!(Template::new("abc").pieces, [literal("abc")]);
+        assert_eq!(
+            Template::new("a$1b$23").pieces,
+            [
+                literal("a"),
+                Piece::Group(1),
+                literal("b"),
+                Piece::Group(23)
+            ]
+        );
+        assert_eq!(
+            Template::new("${1}0${name}").pieces,
+            [Piece::Group(1), literal("0"), Piece::Named("name".into())]
+        );
+        assert_eq!(Template::new("$$1").pieces, [literal("$1")]);
+        assert_eq!(Template::new("$").pieces, [literal("$")]);
+        assert_eq!(Template::new("$x ${} ${a").pieces, [literal("$x ${} ${a")]);
+        assert_eq!(Template::new("${+1}").pieces, [Piece::Named("+1".into())]);
+
+        let pieces: Vec<_> = parse("a$$$1").collect();
+        assert_eq!(
+            pieces,
+            [Piece::Literal("a"), Piece::Literal("$"), Piece::Group(1)]
+        );
+    }
+
+    #[test]
+    fn test_needs_groups() {
+        assert!(!(&Template::new("a$$1")).needs_groups());
+        assert!(!(&Template::new("[$0]")).needs_groups());
+        assert!((&Template::new("$1")).needs_groups());
+        assert!((&Template::new("${x}")).needs_groups());
+
+        assert!(!"a$$1 $0".needs_groups());
+        assert!("${x}".to_string().needs_groups());
+        assert!(!NoExpand("$1").needs_groups());
+        assert!((|_: &Captures| "").needs_groups());
+    }
+}
@@ -1,10 +1,20 @@
 use super::errors;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
-    Char { val: Char, pos: usize },
-    Quantifier { val: Quantifier, pos: usize },
-    Anchor { val: Anchor, pos: usize },
+    Char {
+        val: Char,
+        pos: usize,
+    },
+    Quantifier {
+        val: Quantifier,
+        greedy: bool,
+        pos: usize,
+    },
+    Anchor {
+        val: Anchor,
+        pos: usize,
+    },
 }
 
 impl Token {
@@ -22,16 +32,59 @@ pub enum Quantifier {
     Any,
     Many,
     Maybe,
+    /// `{n}`, `{n,}` or `{n,m}`, with `max` unbounded for `{n,}`
+    Range {
+        min: u32,
+        max: Option<u32>,
+    },
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+impl Quantifier {
+    /// Minimum and maximum number of repetitions, `None` meaning unbounded.
+    pub fn bounds(self) -> (u32, Option<u32>) {
+        match self {
+            Quantifier::Any => (0, None),
+            Quantifier::Many => (1, None),
+            Quantifier::Maybe => (0, Some(1)),
+            Quantifier::Range { min, max } => (min, max),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Anchor {
     Start,
     End,
     CharClassStart,
     CharClassEnd,
+    /// `^` right after the start of a char class
+    CharClassNegate,
+    /// Unescaped `-` inside a char class
+    CharClassRange,
     GroupStart,
+    NamedGroupStart(String),
+    NonCapturingGroupStart,
+    /// `(?flags:`, a non-capturing group with its own flags
+    FlagGroupStart(Flags),
+    /// `(?flags)`, changing the flags until the end of the enclosing group
+    SetFlags(Flags),
     GroupEnd,
+    Alternation,
+}
+
+/// Inline flags, each `None` when left unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Flags {
+    /// `i`: letters match regardless of their case
+    pub case_insensitive: Option<bool>,
+    /// `m`: `^` and `$` also match at the start and end of lines
+    pub multi_line: Option<bool>,
+    /// `s`: `.` also matches `\n`
+    pub dot_matches_new_line: Option<bool>,
+    /// `x`: whitespace and `#` comments in the pattern are ignored
+    pub ignore_whitespace: Option<bool>,
+    /// `u`: Unicode-aware `\d`, `\w` and `\s`
+    pub unicode: Option<bool>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -39,6 +92,8 @@ pub enum Char {
     Lit(char),
     Dot,
     Escape(EscapeChar),
+    /// `\xHH`, either the char U+00HH or a raw byte depending on the mode
+    Byte(u8),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -69,107 +124,115 @@ impl EscapeChar {
             _ => None,
         }
     }
+
+    /// The char an escape stands for, if it is not a class.
+    pub fn as_char(self) -> Option<char> {
+        match self {
+            Self::Newline => Some('\n'),
+            Self::Tab => Some('\t'),
+            Self::Null => Some('\0'),
+            _ => None,
+        }
+    }
 }
 
 pub fn tokenize(pattern: String) -> Result<Vec<Token>, errors::ParseError> {
+    tokenize_with(pattern, false)
+}
+
+/// Like [`tokenize`], but skips whitespace and `#` comments outside char
+/// classes from the start when `ignore_whitespace`, as the `x` flag does.
+pub fn tokenize_with(
+    pattern: String,
+    mut ignore_whitespace: bool,
+) -> Result<Vec<Token>, errors::ParseError> {
     let mut tokens: Vec<Token> = vec![];
 
     let mut chars = pattern.char_indices();
+    let mut in_char_class = false;
+    // Whether whitespace was ignored outside each enclosing group
+    let mut scopes = vec![];
     while let Some((pos, val)) = chars.next() {
-        let token = match val {
-            '\\' => match chars.next() {
-                Some((_, '\\')) => Token::Char {
-                    val: Char::Lit('\\'),
-                    pos,
-                },
-                Some((_, '$')) => Token::Char {
-                    val: Char::Lit('$'),
-                    pos,
-                },
-                Some((_, '^')) => Token::Char {
-                    val: Char::Lit('^'),
-                    pos,
-                },
-                Some((_, '.')) => Token::Char {
-                    val: Char::Lit('.'),
-                    pos,
-                },
-                Some((_, '*')) => Token::Char {
-                    val: Char::Lit('*'),
-                    pos,
-                },
-                Some((_, '+')) => Token::Char {
-                    val: Char::Lit('+'),
-                    pos,
-                },
-                Some((_, '?')) => Token::Char {
-                    val: Char::Lit('?'),
-                    pos,
-                },
-                Some((_, '[')) => Token::Char {
-                    val: Char::Lit('['),
-                    pos,
-                },
-                Some((_, ']')) => Token::Char {
-                    val: Char::Lit(']'),
-                    pos,
-                },
-                Some((_, '(')) => Token::Char {
-                    val: Char::Lit('('),
-                    pos,
-                },
-                Some((_, ')')) => Token::Char {
-                    val: Char::Lit(')'),
-                    pos,
-                },
-                Some((_, '{')) => Token::Char {
-                    val: Char::Lit('{'),
-                    pos,
-                },
-                Some((_, '}')) => Token::Char {
-                    val: Char::Lit('}'),
-                    pos,
-                },
-                Some((_, '|')) => Token::Char {
-                    val: Char::Lit('|'),
-                    pos,
-                },
-                Some((_, c)) => {
-                    if let Some(escape_char) = EscapeChar::from_char(c) {
-                        Token::Char {
-                            val: Char::Escape(escape_char),
-                            pos,
-                        }
-                    } else {
-                        return Err(errors::ParseError::BadEscapeSequence(
-                            pos,
-                            format!("Unknown escape sequence: \\{}", c),
-                        ));
+        // Inside a char class only escapes, `]`, `-` and a leading `^` are
+        // special, everything else is a plain char
+        if in_char_class {
+            let token = match val {
+                '\\' => tokenize_escape(pos, &mut chars)?,
+                ']' => {
+                    in_char_class = false;
+                    Token::Anchor {
+                        val: Anchor::CharClassEnd,
+                        pos,
                     }
                 }
-                None => {
-                    return Err(errors::ParseError::BadEscapeSequence(
+                '^' if matches!(
+                    tokens.last(),
+                    Some(Token::Anchor {
+                        val: Anchor::CharClassStart,
+                        ..
+                    })
+                ) =>
+                {
+                    Token::Anchor {
+                        val: Anchor::CharClassNegate,
                         pos,
-                        "Expected a character after the escape sequence".into(),
-                    ))
+                    }
                 }
-            },
+                '-' => Token::Anchor {
+                    val: Anchor::CharClassRange,
+                    pos,
+                },
+                c => Token::Char {
+                    val: Char::Lit(c),
+                    pos,
+                },
+            };
+
+            tokens.push(token);
+            continue;
+        }
+
+        if ignore_whitespace && val.is_whitespace() {
+            continue;
+        }
+        if ignore_whitespace && val == '#' {
+            chars.find(|&(_, c)| c == '\n');
+            continue;
+        }
+
+        let token = match val {
+            '\\' => tokenize_escape(pos, &mut chars)?,
             '.' => Token::Char {
                 val: Char::Dot,
                 pos,
             },
             '*' => Token::Quantifier {
                 val: Quantifier::Any,
+                greedy: true,
                 pos,
             },
             '+' => Token::Quantifier {
                 val: Quantifier::Many,
+                greedy: true,
                 pos,
             },
             '?' => Token::Quantifier {
                 val: Quantifier::Maybe,
+                greedy: true,
                 pos,
             },
+            '{' => match tokenize_repetition(pos, &mut chars)? {
+                Some(val) => Token::Quantifier {
+                    val,
+                    greedy: true,
+                    pos,
+                },
+                // Braces that do not form a repetition are plain chars
+                None => Token::Char {
+                    val: Char::Lit('{'),
+                    pos,
+                },
+            },
             '^' => Token::Anchor {
                 val: Anchor::Start,
                 pos,
@@ -178,34 +241,340 @@ pub fn tokenize(pattern: String) -> Result<Vec<Token>, errors::ParseError> {
                 val: Anchor::End,
                 pos,
             },
-            '[' => Token::Anchor {
-                val: Anchor::CharClassStart,
-                pos,
-            },
+            '[' => {
+                in_char_class = true;
+                Token::Anchor {
+                    val: Anchor::CharClassStart,
+                    pos,
+                }
+            }
             ']' => Token::Anchor {
                 val: Anchor::CharClassEnd,
                 pos,
             },
-            '(' => Token::Anchor {
-                val: Anchor::GroupStart,
-                pos,
-            },
+            '(' => {
+                let rest = chars.as_str();
+                match ["?P<", "?<"].iter().find(|p| rest.starts_with(*p)) {
+                    Some(prefix) => {
+                        chars.nth(prefix.len() - 1);
+                        Token::Anchor {
+                            val: Anchor::NamedGroupStart(tokenize_group_name(pos, &mut chars)?),
+                            pos,
+                        }
+                    }
+                    None if rest.starts_with("?:") => {
+                        chars.nth(1);
+                        Token::Anchor {
+                            val: Anchor::NonCapturingGroupStart,
+                            pos,
+                        }
+                    }
+                    None if rest.starts_with('?') => {
+                        chars.next();
+                        tokenize_flags(pos, &mut chars)?
+                    }
+                    None => Token::Anchor {
+                        val: Anchor::GroupStart,
+                        pos,
+                    },
+                }
+            }
             ')' => Token::Anchor {
                 val: Anchor::GroupEnd,
                 pos,
             },
+            '|' => Token::Anchor {
+                val: Anchor::Alternation,
+                pos,
+            },
             c => Token::Char {
                 val: Char::Lit(c),
                 pos,
             },
         };
 
+        // A `?` right after a quantifier makes it lazy
+        let token = match token {
+            Token::Quantifier { val, pos, .. } if chars.as_str().starts_with('?') => {
+                chars.next();
+                Token::Quantifier {
+                    val,
+                    greedy: false,
+                    pos,
+                }
+            }
+            token => token,
+        };
+
+        // Flags set inside a group do not outlive it
+        match &token {
+            Token::Anchor {
+                val:
+                    Anchor::GroupStart | Anchor::NamedGroupStart(_) | Anchor::NonCapturingGroupStart,
+                ..
+            } => scopes.push(ignore_whitespace),
+            Token::Anchor {
+                val: Anchor::FlagGroupStart(flags),
+                ..
+            } => {
+                scopes.push(ignore_whitespace);
+                ignore_whitespace = flags.ignore_whitespace.unwrap_or(ignore_whitespace);
+            }
+            Token::Anchor {
+                val: Anchor::SetFlags(flags),
+                ..
+            } => ignore_whitespace = flags.ignore_whitespace.unwrap_or(ignore_whitespace),
+            Token::Anchor {
+                val: Anchor::GroupEnd,
+                ..
+            } => ignore_whitespace = scopes.pop().unwrap_or(ignore_whitespace),
+            _ => {}
+        }
+
         tokens.push(token);
     }
 
     Ok(tokens)
 }
 
+/// Reads the char following a `\`, which either escapes a special char or
+/// names an escape class.
+fn tokenize_escape(
+    pos: usize,
+    chars: &mut std::str::CharIndices,
+) -> Result<Token, errors::ParseError> {
+    let token = match chars.next() {
+        Some((_, '\\')) => Token::Char {
+            val: Char::Lit('\\'),
+            pos,
+        },
+        Some((_, '$')) => Token::Char {
+            val: Char::Lit('$'),
+            pos,
+        },
+        Some((_, '^')) => Token::Char {
+            val: Char::Lit('^'),
+            pos,
+        },
+        Some((_, '.')) => Token::Char {
+            val: Char::Lit('.'),
+            pos,
+        },
+        Some((_, '*')) => Token::Char {
+            val: Char::Lit('*'),
+            pos,
+        },
+        Some((_, '+')) => Token::Char {
+            val: Char::Lit('+'),
+            pos,
+        },
+        Some((_, '?')) => Token::Char {
+            val: Char::Lit('?'),
+            pos,
+        },
+        Some((_, '[')) => Token::Char {
+            val: Char::Lit('['),
+            pos,
+        },
+        Some((_, ']')) => Token::Char {
+            val: Char::Lit(']'),
+            pos,
+        },
+        Some((_, '(')) => Token::Char {
+            val: Char::Lit('('),
+            pos,
+        },
+        Some((_, ')')) => Token::Char {
+            val: Char::Lit(')'),
+            pos,
+        },
+        Some((_, '{')) => Token::Char {
+            val: Char::Lit('{'),
+            pos,
+        },
+        Some((_, '}')) => Token::Char {
+            val: Char::Lit('}'),
+            pos,
+        },
+        Some((_, '|')) => Token::Char {
+            val: Char::Lit('|'),
+            pos,
+        },
+        Some((_, '-')) => Token::Char {
+            val: Char::Lit('-'),
+            pos,
+        },
+        // Only special when ignoring whitespace
+        Some((_, c @ (' ' | '#'))) => Token::Char {
+            val: Char::Lit(c),
+            pos,
+        },
+        Some((_, 'x')) => {
+            let rest = chars.as_str();
+            let hex = rest
+                .get(..2)
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()));
+            let Some(hex) = hex else {
+                return Err(errors::ParseError::BadEscapeSequence(
+                    pos,
+                    "two hex digits after \\x".into(),
+                ));
+            };
+            chars.nth(1);
+            Token::Char {
+                val: Char::Byte(u8::from_str_radix(hex, 16).unwrap()),
+                pos,
+            }
+        }
+        Some((_, c)) => {
+            if let Some(escape_char) = EscapeChar::from_char(c) {
+                Token::Char {
+                    val: Char::Escape(escape_char),
+                    pos,
+                }
+            } else {
+                return Err(errors::ParseError::BadEscapeSequence(
+                    pos,
+                    format!("Unknown escape sequence: \\{}", c),
+                ));
+            }
+        }
+        None => {
+            return Err(errors::ParseError::BadEscapeSequence(
+                pos,
+                "Expected a character after the escape sequence".into(),
+            ))
+        }
+    };
+
+    Ok(token)
+}
+
+/// Reads the `n}`, `n,}` or `n,m}` following a `{`, only advancing `chars`
+/// if it forms a counted repetition. Counts too large for a `u32` saturate
+/// and are left for the parser to reject.
+fn tokenize_repetition(
+    pos: usize,
+    chars: &mut std::str::CharIndices,
+) -> Result<Option<Quantifier>, errors::ParseError> {
+    let rest = chars.as_str();
+    let Some(end) = rest.find('}') else {
+        return Ok(None);
+    };
+
+    let body = &rest[..end];
+    let (min, max) = match body.split_once(',') {
+        None => (body, Some(body)),
+        Some((min, "")) => (min, None),
+        Some((min, max)) => (min, Some(max)),
+    };
+
+    let is_count = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if !is_count(min) || !max.is_none_or(is_count) {
+        return Ok(None);
+    }
+
+    let count = |s: &str| s.parse().unwrap_or(u32::MAX);
+    let (min, max) = (count(min), max.map(count));
+    if max.is_some_and(|max| max < min) {
+        return Err(errors::ParseError::BadRepetition(
+            pos,
+            format!("{{{}}} has a minimum greater than its maximum", body),
+        ));
+    }
+
+    // The body is only made of ASCII digits and a comma, one byte per char
+    chars.nth(end);
+    Ok(Some(Quantifier::Range { min, max }))
+}
+
+/// Reads the flags and the `)` or `:` following a `(?`.
+fn tokenize_flags(
+    pos: usize,
+    chars: &mut std::str::CharIndices,
+) -> Result<Token, errors::ParseError> {
+    let mut flags = Flags::default();
+    let mut enable = true;
+    let mut empty = true;
+
+    for (_, c) in chars.by_ref() {
+        let val = match c {
+            ')' | ':' if empty => break,
+            ')' => Anchor::SetFlags(flags),
+            ':' => Anchor::FlagGroupStart(flags),
+            '-' if enable => {
+                enable = false;
+                continue;
+            }
+            'i' | 'm' | 's' | 'x' | 'u' => {
+                let flag = match c {
+                    'i' => &mut flags.case_insensitive,
+                    'm' => &mut flags.multi_line,
+                    's' => &mut flags.dot_matches_new_line,
+                    'x' => &mut flags.ignore_whitespace,
+                    _ => &mut flags.unicode,
+                };
+                *flag = Some(enable);
+                empty = false;
+                continue;
+            }
+            c => {
+                return Err(errors::ParseError::BadFlag(
+                    pos,
+                    format!("Unknown flag: {}", c),
+                ))
+            }
+        };
+        return Ok(Token::Anchor { val, pos });
+    }
+
+    Err(errors::ParseError::BadFlag(
+        pos,
+        "Expected flags followed by a ) or a :".into(),
+    ))
+}
+
+/// Reads a group name up to and including the closing `>`. Names must start
+/// with a letter or `_` and only contain letters, digits and `_`.
+fn tokenize_group_name(
+    pos: usize,
+    chars: &mut std::str::CharIndices,
+) -> Result<String, errors::ParseError> {
+    let mut name = String::new();
+
+    for (_, c) in chars.by_ref() {
+        match c {
+            '>' if name.is_empty() => {
+                return Err(errors::ParseError::BadGroupName(
+                    pos,
+                    "Group name cannot be empty".into(),
+                ))
+            }
+            '>' => return Ok(name),
+            c if c.is_alphanumeric() || c == '_' => {
+                if name.is_empty() && c.is_numeric() {
+                    return Err(errors::ParseError::BadGroupName(
+                        pos,
+                        format!("Group name cannot start with a digit: {}", c),
+                    ));
+                }
+                name.push(c);
+            }
+            c => {
+                return Err(errors::ParseError::BadGroupName(
+                    pos,
+                    format!("Invalid character in group name: {}", c),
+                ))
+            }
+        }
+    }
+
+    Err(errors::ParseError::BadGroupName(
+        pos,
+        "Expected a > after the group name".into(),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -231,6 +600,44 @@ mod tests {
     #[test]
     fn test_bad_escape() {
         tokenize("\\a".to_string()).unwrap_err();
+        tokenize("\\x".to_string()).unwrap_err();
+        tokenize("\\xF".to_string()).unwrap_err();
+        tokenize("\\xFG".to_string()).unwrap_err();
+        tokenize("\\xé0".to_string()).unwrap_err();
+    }
+
+    #[test]
+    fn test_byte_escape() {
+        let tokens = tokenize("\\xfF[\\x00-\\x7f]".to_string()).unwrap();
+
+        let expected_tokens = vec![
+            Token::Char {
+                val: Char::Byte(0xFF),
+                pos: 0,
+            },
+            Token::Anchor {
+                val: Anchor::CharClassStart,
+                pos: 4,
+            },
+            Token::Char {
+                val: Char::Byte(0x00),
+                pos: 5,
+            },
+            Token::Anchor {
+                val: Anchor::CharClassRange,
+                pos: 9,
+            },
+            Token::Char {
+                val: Char::Byte(0x7F),
+                pos: 10,
+            },
+            Token::Anchor {
+                val: Anchor::CharClassEnd,
+                pos: 14,
+            },
+        ];
+
+        assert_eq!(tokens, expected_tokens);
     }
 
     #[test]
@@ -327,6 +734,13 @@ mod tests {
                     pos: 0,
                 },
             ),
+            (
+                "\\-",
+                Token::Char {
+                    val: Char::Lit('-'),
+                    pos: 0,
+                },
+            ),
         ];
 
         for (input, expected_token) in test_cases {
@@ -351,6 +765,7 @@ mod tests {
                 "*",
                 Token::Quantifier {
                     val: Quantifier::Any,
+                    greedy: true,
                     pos: 0,
                 },
             ),
@@ -358,6 +773,7 @@ mod tests {
                 "+",
                 Token::Quantifier {
                     val: Quantifier::Many,
+                    greedy: true,
                     pos: 0,
                 },
             ),
@@ -365,6 +781,7 @@ mod tests {
                 "?",
                 Token::Quantifier {
                     val: Quantifier::Maybe,
+                    greedy: true,
                     pos: 0,
                 },
             ),
@@ -410,6 +827,13 @@ mod tests {
                     pos: 0,
                 },
             ),
+            (
+                "|",
+                Token::Anchor {
+                    val: Anchor::Alternation,
+                    pos: 0,
+                },
+            ),
         ];
 
         for (input, expected_token) in test_cases {
@@ -418,4 +842,326 @@ mod tests {
             assert_eq!(tokens[0], expected_token);
         }
     }
+
+    #[test]
+    fn test_named_group() {
+        for pattern in ["(?P<year>", "(?<year>"] {
+            assert_eq!(
+                tokenize(pattern.to_string()).unwrap(),
+                vec![Token::Anchor {
+                    val: Anchor::NamedGroupStart("year".into()),
+                    pos: 0,
+                }]
+            );
+        }
+
+        assert_eq!(
+            tokenize("(?<_a1>b".to_string()).unwrap(),
+            vec![
+                Token::Anchor {
+                    val: Anchor::NamedGroupStart("_a1".into()),
+                    pos: 0,
+                },
+                Token::Char {
+                    val: Char::Lit('b'),
+                    pos: 7,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_non_capturing_group() {
+        assert_eq!(
+            tokenize("(?:a)".to_string()).unwrap(),
+            vec![
+                Token::Anchor {
+                    val: Anchor::NonCapturingGroupStart,
+                    pos: 0,
+                },
+                Token::Char {
+                    val: Char::Lit('a'),
+                    pos: 3,
+                },
+                Token::Anchor {
+                    val: Anchor::GroupEnd,
+                    pos: 4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_counted_repetition() {
+        let test_cases = vec![
+            (
+                "{3}",
+                Quantifier::Range {
+                    min: 3,
+                    max: Some(3),
+                },
+            ),
+            ("{3,}", Quantifier::Range { min: 3, max: None }),
+            (
+                "{3,5}",
+                Quantifier::Range {
+                    min: 3,
+                    max: Some(5),
+                },
+            ),
+            (
+                "{0,12}",
+                Quantifier::Range {
+                    min: 0,
+                    max: Some(12),
+                },
+            ),
+        ];
+
+        for (input, expected_quantifier) in test_cases {
+            let tokens = tokenize(input.to_string()).unwrap();
+            assert_eq!(
+                tokens,
+                vec![Token::Quantifier {
+                    val: expected_quantifier,
+                    greedy: true,
+                    pos: 0,
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn test_char_class() {
+        let tokens = tokenize("[^a-z.*(-]-".to_string()).unwrap();
+
+        let expected_tokens = vec![
+            Token::Anchor {
+                val: Anchor::CharClassStart,
+                pos: 0,
+            },
+            Token::Anchor {
+                val: Anchor::CharClassNegate,
+                pos: 1,
+            },
+            Token::Char {
+                val: Char::Lit('a'),
+                pos: 2,
+            },
+            Token::Anchor {
+                val: Anchor::CharClassRange,
+                pos: 3,
+            },
+            Token::Char {
+                val: Char::Lit('z'),
+                pos: 4,
+            },
+            Token::Char {
+                val: Char::Lit('.'),
+                pos: 5,
+            },
+            Token::Char {
+                val: Char::Lit('*'),
+                pos: 6,
+            },
+            Token::Char {
+                val: Char::Lit('('),
+                pos: 7,
+            },
+            Token::Anchor {
+                val: Anchor::CharClassRange,
+                pos: 8,
+            },
+            Token::Anchor {
+                val: Anchor::CharClassEnd,
+                pos: 9,
+            },
+            Token::Char {
+                val: Char::Lit('-'),
+                pos: 10,
+            },
+        ];
+
+        assert_eq!(tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_char_class_escapes() {
+        let tokens = tokenize("[a^\\]\\-]".to_string()).unwrap();
+
+        let expected_tokens = vec![
+            Token::Anchor {
+                val: Anchor::CharClassStart,
+                pos: 0,
+            },
+            Token::Char {
+                val: Char::Lit('a'),
+                pos: 1,
+            },
+            Token::Char {
+                val: Char::Lit('^'),
+                pos: 2,
+            },
+            Token::Char {
+                val: Char::Lit(']'),
+                pos: 3,
+            },
+            Token::Char {
+                val: Char::Lit('-'),
+                pos: 5,
+            },
+            Token::Anchor {
+                val: Anchor::CharClassEnd,
+                pos: 7,
+            },
+        ];
+
+        assert_eq!(tokens, expected_tokens);
+    }
+
+    #[test]
+    fn test_lazy_quantifiers() {
+        let test_cases = vec![
+            ("*?", Quantifier::Any),
+            ("+?", Quantifier::Many),
+            ("??", Quantifier::Maybe),
+            (
+                "{2,5}?",
+                Quantifier::Range {
+                    min: 2,
+                    max: Some(5),
+                },
+            ),
+        ];
+
+        for (input, expected_quantifier) in test_cases {
+            let tokens = tokenize(input.to_string()).unwrap();
+            assert_eq!(
+                tokens,
+                vec![Token::Quantifier {
+                    val: expected_quantifier,
+                    greedy: false,
+                    pos: 0,
+                }]
+            );
+        }
+
+        // Only the first `?` is taken as the lazy modifier
+        let tokens = tokenize("a*??".to_string()).unwrap();
+        assert_eq!(tokens.len(), 3);
+    }
+
+    #[test]
+    fn test_literal_braces() {
+        for input in ["{", "{}", "{a}", "{,3}", "{3", "{3,a}", "{ 3}"] {
+            let tokens = tokenize(input.to_string()).unwrap();
+            assert_eq!(
+                tokens[0],
+                Token::Char {
+                    val: Char::Lit('{'),
+                    pos: 0,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_bad_repetition() {
+        let err = tokenize("a{3,1}".to_string()).unwrap_err();
+        assert!(matches!(err, errors::ParseError::BadRepetition(1, _)));
+    }
+
+    #[test]
+    fn test_bad_group_name() {
+        for pattern in ["(?<>a)", "(?P<1a>a)", "(?<a-b>a)", "(?<abc", "(?<=a)"] {
+            let err = tokenize(pattern.to_string()).unwrap_err();
+            assert!(matches!(err, errors::ParseError::BadGroupName(0, _)));
+        }
+    }
+
+    #[test]
+    fn test_flags() {
+        let tokens = tokenize("(?-u)(?u:a)".to_string()).unwrap();
+
+        let expected_tokens = vec![
+            Token::Anchor {
+                val: Anchor::SetFlags(Flags {
+                    unicode: Some(false),
+                    ..Flags::default()
+                }),
+                pos: 0,
+            },
+            Token::Anchor {
+                val: Anchor::FlagGroupStart(Flags {
+                    unicode: Some(true),
+                    ..Flags::default()
+                }),
+                pos: 5,
+            },
+            Token::Char {
+                val: Char::Lit('a'),
+                pos: 9,
+            },
+            Token::Anchor {
+                val: Anchor::GroupEnd,
+                pos: 10,
+            },
+        ];
+
+        assert_eq!(tokens, expected_tokens);
+
+        let tokens = tokenize("(?is-mx)".to_string()).unwrap();
+        let flags = Flags {
+            case_insensitive: Some(true),
+            multi_line: Some(false),
+            dot_matches_new_line: Some(true),
+            ignore_whitespace: Some(false),
+            unicode: None,
+        };
+        assert_eq!(
+            tokens,
+            [Token::Anchor {
+                val: Anchor::SetFlags(flags),
+                pos: 0,
+            }]
+        );
+
+        assert!(matches!(
+            tokenize("(?a)".to_string()),
+            Err(errors::ParseError::BadFlag(0, _))
+        ));
+        assert!(matches!(
+            tokenize("(?)".to_string()),
+            Err(errors::ParseError::BadFlag(0, _))
+        ));
+        assert!(matches!(
+            tokenize("(?u".to_string()),
+            Err(errors::ParseError::BadFlag(0, _))
+        ));
+    }
+
+    #[test]
+    fn test_ignore_whitespace() {
+        let chars = |tokens: Vec<Token>| {
+            tokens
+                .into_iter()
+                .filter_map(|tok| match tok {
+                    Token::Char {
+                        val: Char::Lit(c), ..
+                    } => Some(c),
+                    _ => None,
+                })
+                .collect::<String>()
+        };
+
+        let tokens = tokenize_with("a b # comment\n c\\ d[ e]\\#".to_string(), true).unwrap();
+        assert_eq!(chars(tokens), "abc d e#");
+
+        // The flag only lasts until the end of its group
+        let tokens = tokenize("a b(?x: c d (e f) ) g (?x)h i".to_string()).unwrap();
+        assert_eq!(chars(tokens), "a bcdef g hi");
+
+        let tokens = tokenize_with("a (?-x) b".to_string(), true).unwrap();
+        assert_eq!(chars(tokens), "a b");
+    }
 }
@@ -0,0 +1,98 @@
+/// A sequence of byte ranges matching the UTF-8 encoding of a range of
+/// chars: a char matches when each of its bytes falls in the range at the
+/// same index.
+pub type Sequence = Vec<(u8, u8)>;
+
+/// Splits the inclusive char range `start..=end` into byte range sequences
+/// matching exactly the UTF-8 encodings of its chars. The sequences come
+/// in order, and any two of them either start with the same byte range or
+/// with disjoint ones.
+pub fn sequences(start: char, end: char) -> Vec<Sequence> {
+    let mut sequences = vec![];
+    let mut ranges = vec![(start as u32, end as u32)];
+
+    'ranges: while let Some((start, end)) = ranges.pop() {
+        // Surrogates have no encoding, and the encoded length must not
+        // change within a range
+        if start < 0xD800 && end > 0xDFFF {
+            ranges.push((0xE000, end));
+            ranges.push((start, 0xD7FF));
+            continue;
+        }
+        for max in [0x7F, 0x7FF, 0xFFFF] {
+            if start <= max && max < end {
+                ranges.push((max + 1, end));
+                ranges.push((start, max));
+                continue 'ranges;
+            }
+        }
+
+        // Split until every continuation byte either spans its full range
+        // or is the same at both ends
+        for i in 1..4 {
+            let mask = (1 << (6 * i)) - 1;
+            if start & !mask != end & !mask {
+                if start & mask != 0 {
+                    ranges.push(((start | mask) + 1, end));
+                    ranges.push((start, start | mask));
+                    continue 'ranges;
+                }
+                if end & mask != mask {
+                    ranges.push((end & !mask, end));
+                    ranges.push((start, (end & !mask) - 1));
+                    continue 'ranges;
+                }
+            }
+        }
+
+        let (mut start_buf, mut end_buf) = ([0; 4], [0; 4]);
+        let start = char::from_u32(start).unwrap().encode_utf8(&mut start_buf);
+        let end = char::from_u32(end).unwrap().encode_utf8(&mut end_buf);
+        sequences.push(start.bytes().zip(end.bytes()).collect());
+    }
+
+    sequences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequences() {
+        assert_eq!(sequences('a', 'z'), vec![vec![(b'a', b'z')]]);
+
+        assert_eq!(
+            sequences('\u{0}', '\u{FFFF}'),
+            vec![
+                vec![(0x00, 0x7F)],
+                vec![(0xC2, 0xDF), (0x80, 0xBF)],
+                vec![(0xE0, 0xE0), (0xA0, 0xBF), (0x80, 0xBF)],
+                vec![(0xE1, 0xEC), (0x80, 0xBF), (0x80, 0xBF)],
+                vec![(0xED, 0xED), (0x80, 0x9F), (0x80, 0xBF)],
+                vec![(0xEE, 0xEF), (0x80, 0xBF), (0x80, 0xBF)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sequences_match_exactly() {
+        let ranges = [('\u{0}', char::MAX), ('é', 'ӿ'), ('\u{FFF0}', '\u{10010}')];
+
+        for (start, end) in ranges {
+            let sequences = sequences(start, end);
+            for c in (0..=0x10FFFF).filter_map(char::from_u32) {
+                let mut buf = [0; 4];
+                let bytes = c.encode_utf8(&mut buf).as_bytes();
+                let matches = sequences.iter().any(|seq| {
+                    seq.len() == bytes.len()
+                        && seq
+                            .iter()
+                            .zip(bytes)
+                            .all(|(&(lo, hi), &b)| lo <= b && b <= hi)
+                });
+                assert_eq!(matches, start <= c && c <= end, "{:?}", c);
+            }
+        }
+    }
+}